@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::path::Path;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use eframe::CreationContext;
@@ -15,23 +17,31 @@ use egui::Key;
 use egui::Layout;
 use egui::Sense;
 use egui::TextureHandle;
-use egui::TextureOptions;
 use egui::Ui;
+use log::error;
+use log::info;
 use sres_emulator::components::cartridge::Cartridge;
 use sres_emulator::components::ppu::Framebuffer;
 use sres_emulator::controller::StandardController;
+use sres_emulator::recording::WavRecorder;
+use sres_emulator::recording::Y4mWavRecorder;
 use sres_emulator::System;
+use strum::VariantArray;
 
 use crate::audio::AudioOutput;
 use crate::debug::DebugUi;
 use crate::home;
+use crate::spc_player::SpcPlayer;
+use crate::util::unix_timestamp;
 use crate::util::EguiImageImpl;
 use crate::util::Instant;
 use crate::util::RingBuffer;
+use crate::video_filter::VideoFilter;
 
 pub struct EmulatorApp {
     emulator: System,
     loaded_cartridge: Option<Cartridge>,
+    spc_player: Option<SpcPlayer>,
     framebuffer_texture: TextureHandle,
     debug_ui: DebugUi,
     past_frame_times: RingBuffer<Duration, 60>,
@@ -41,16 +51,22 @@ pub struct EmulatorApp {
     input_recording_active: bool,
     input_recording_last: u16,
     input_recording: HashMap<u64, u16>,
+
+    rom_path: Option<PathBuf>,
+    screenshot_scale: u32,
+    video_filter: VideoFilter,
+    displayed_video_filter: VideoFilter,
 }
 
 impl EmulatorApp {
     /// Called once before the first frame.
-    pub fn new(cc: &CreationContext<'_>, cartridge: Option<Cartridge>) -> Self {
+    pub fn new(cc: &CreationContext<'_>, rom_path: Option<PathBuf>) -> Self {
         egui_extras::install_image_loaders(&cc.egui_ctx);
         cc.egui_ctx.set_visuals(egui::Visuals::dark());
         let mut app = EmulatorApp {
             emulator: System::new(),
             loaded_cartridge: None,
+            spc_player: None,
             framebuffer_texture: cc.egui_ctx.load_texture(
                 "Framebuffer",
                 ColorImage::filled([32, 32], Color32::BLACK),
@@ -63,10 +79,17 @@ impl EmulatorApp {
             input_recording: HashMap::new(),
             input_recording_last: 0,
             input_recording_active: false,
+            rom_path: None,
+            screenshot_scale: 1,
+            video_filter: VideoFilter::default(),
+            displayed_video_filter: VideoFilter::default(),
         };
 
-        if let Some(rom) = cartridge {
-            app.load_cartridge(rom);
+        if let Some(path) = rom_path {
+            match path.extension().and_then(OsStr::to_str) {
+                Some("spc") => app.load_spc_file(&path),
+                _ => app.load_rom_file(&path),
+            }
         }
         app
     }
@@ -75,6 +98,8 @@ impl EmulatorApp {
         self.emulator = System::with_cartridge(&cartridge);
         self.emulator.debugger().enable();
         self.loaded_cartridge = Some(cartridge);
+        self.spc_player = None;
+        self.rom_path = None;
         // Start audio output when a cartridge is loaded
         self.audio_output.start();
     }
@@ -82,20 +107,74 @@ impl EmulatorApp {
     fn load_dropped_file(&mut self, drop: &DroppedFile) {
         if let Some(path) = &drop.path {
             match path.extension().and_then(OsStr::to_str) {
-                Some("sfc") => {
-                    self.load_cartridge(Cartridge::with_sfc_file(path).unwrap());
-                }
+                Some("sfc") => self.load_rom_file(path),
+                Some("spc") => self.load_spc_file(path),
                 _ => {
                     panic!("Unknown file type");
                 }
             }
         } else if let Some(bytes) = &drop.bytes {
+            if drop.name.ends_with(".spc") {
+                match SpcPlayer::with_spc_data(bytes, drop.name.clone()) {
+                    Ok(player) => self.play_spc(player),
+                    Err(err) => error!("Failed to load SPC file: {err}"),
+                }
+                return;
+            }
             //#[cfg(target_arch = "wasm32")]
             //crate::wasm::save_rom_in_local_storage(bytes);
             self.load_cartridge(Cartridge::with_sfc_data(bytes, None).unwrap());
         }
     }
 
+    fn load_spc_file(&mut self, path: &Path) {
+        match SpcPlayer::load(path) {
+            Ok(player) => self.play_spc(player),
+            Err(err) => error!("Failed to load SPC file: {err}"),
+        }
+    }
+
+    fn play_spc(&mut self, player: SpcPlayer) {
+        self.loaded_cartridge = None;
+        self.spc_player = Some(player);
+        self.audio_output.start();
+    }
+
+    fn spc_player_ui(&mut self, ctx: &Context) {
+        let Some(player) = self.spc_player.as_mut() else {
+            return;
+        };
+        player.update(&mut self.audio_output);
+        if !player.ui(ctx) {
+            self.spc_player = None;
+        }
+        ctx.request_repaint()
+    }
+
+    fn load_rom_file(&mut self, path: &Path) {
+        self.load_cartridge(Cartridge::with_sfc_file(path).unwrap());
+        self.rom_path = Some(path.to_path_buf());
+    }
+
+    /// Saves the last frame as a timestamped PNG next to the ROM file.
+    fn save_screenshot(&self) {
+        let timestamp = unix_timestamp();
+        let path = match &self.rom_path {
+            Some(rom_path) => {
+                let stem = rom_path.file_stem().unwrap_or_default().to_string_lossy();
+                rom_path.with_file_name(format!("{stem}-{timestamp}.png"))
+            }
+            None => PathBuf::from(format!("screenshot-{timestamp}.png")),
+        };
+        match self
+            .video_frame_buffer
+            .save_png(&path, self.screenshot_scale)
+        {
+            Ok(()) => info!("Saved screenshot to {path:?}"),
+            Err(err) => error!("Failed to save screenshot: {err}"),
+        }
+    }
+
     fn update_keys(&mut self, input: &InputState) {
         let joy1 = StandardController {
             right: input.key_down(Key::ArrowRight),
@@ -153,15 +232,63 @@ impl EmulatorApp {
                     self.input_recording_active = true;
                     self.input_recording.clear();
                 }
+                egui::ComboBox::from_id_salt("video_filter")
+                    .selected_text(self.video_filter.to_string())
+                    .show_ui(ui, |ui| {
+                        for filter in VideoFilter::VARIANTS {
+                            ui.selectable_value(
+                                &mut self.video_filter,
+                                *filter,
+                                filter.to_string(),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text("Video filter");
+                if ui.button("Screenshot").on_hover_text("F12").clicked() {
+                    self.save_screenshot();
+                }
+                ui.add(
+                    egui::DragValue::new(&mut self.screenshot_scale)
+                        .range(1..=4)
+                        .suffix("x"),
+                )
+                .on_hover_text("Screenshot scale");
+                if self.emulator.has_frame_sink() {
+                    if ui.button("Stop Recording").clicked() {
+                        if let Err(err) = self.emulator.remove_frame_sink() {
+                            error!("Failed to save recording: {err}");
+                        }
+                    }
+                } else {
+                    if ui.button("Record Audio").clicked() {
+                        let path = PathBuf::from(format!("audio-{}.wav", unix_timestamp()));
+                        match WavRecorder::create(&path) {
+                            Ok(recorder) => self.emulator.set_frame_sink(Box::new(recorder)),
+                            Err(err) => error!("Failed to start audio recording: {err}"),
+                        }
+                    }
+                    if ui.button("Record Video").clicked() {
+                        let path = PathBuf::from(format!("video-{}", unix_timestamp()));
+                        match Y4mWavRecorder::create(&path) {
+                            Ok(recorder) => self.emulator.set_frame_sink(Box::new(recorder)),
+                            Err(err) => error!("Failed to start video recording: {err}"),
+                        }
+                    }
+                }
             });
         });
     }
 
     fn main_display(&mut self, ui: &mut Ui) {
-        if self.emulator.swap_video_frame(&mut self.video_frame_buffer) {
-            let video_frame = self.video_frame_buffer.to_rgba::<EguiImageImpl>();
+        let new_frame = self.emulator.swap_video_frame(&mut self.video_frame_buffer);
+        if new_frame || self.video_filter != self.displayed_video_filter {
+            let video_frame = self
+                .video_filter
+                .apply::<EguiImageImpl>(&self.video_frame_buffer);
             self.framebuffer_texture
-                .set(video_frame, TextureOptions::default());
+                .set(video_frame, self.video_filter.texture_options());
+            self.displayed_video_filter = self.video_filter;
         }
 
         let desired_size = ui.available_size();
@@ -179,13 +306,6 @@ impl EmulatorApp {
         puffin::GlobalProfiler::lock().new_frame();
         let start = Instant::now();
 
-        // Load new program if a file is dropped on the app
-        ctx.input(|input| {
-            if !input.raw.dropped_files.is_empty() {
-                self.load_dropped_file(&input.raw.dropped_files[0]);
-            }
-        });
-
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             self.menu_bar(ui);
         });
@@ -195,6 +315,9 @@ impl EmulatorApp {
         ctx.input(|input| {
             self.update_keys(input);
         });
+        if ctx.input(|input| input.key_pressed(Key::F12)) {
+            self.save_screenshot();
+        }
 
         let stable_dt = ctx.input(|input| input.stable_dt as f64);
 
@@ -226,8 +349,24 @@ impl EmulatorApp {
             self.main_display(ui);
         });
 
-        if self.emulator.debugger().enabled() {
-            self.debug_ui.modals(ctx, &mut self.emulator);
+        if let Some(cartridge) = &self.loaded_cartridge {
+            if self.emulator.debugger().enabled() {
+                self.debug_ui.modals(
+                    ctx,
+                    &mut self.emulator,
+                    &self.framebuffer_texture,
+                    &cartridge.header,
+                );
+                // Samples are queued after the emulator output, so they are heard right away
+                // while the emulator is paused.
+                if let Some(samples) = self.debug_ui.take_sample_playback() {
+                    self.audio_output.queue_samples(|buffer| {
+                        for sample in samples {
+                            buffer.push_sample(sample);
+                        }
+                    });
+                }
+            }
         }
 
         self.past_frame_times.push(start.elapsed());
@@ -239,7 +378,14 @@ impl EmulatorApp {
 
 impl eframe::App for EmulatorApp {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
-        if self.loaded_cartridge.is_none() {
+        // Load new program if a file is dropped on the app
+        if let Some(drop) = ctx.input(|input| input.raw.dropped_files.first().cloned()) {
+            self.load_dropped_file(&drop);
+        }
+
+        if self.spc_player.is_some() {
+            self.spc_player_ui(ctx);
+        } else if self.loaded_cartridge.is_none() {
             home::home_screen(ctx, |cartridge| {
                 self.load_cartridge(cartridge);
             });
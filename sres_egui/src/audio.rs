@@ -12,11 +12,17 @@ use cpal::Stream;
 use cpal::StreamConfig;
 use log::error;
 use log::info;
+use sres_emulator::apu::resampler::Resampler;
 use sres_emulator::apu::AudioBuffer;
+use sres_emulator::apu::APU_SAMPLE_RATE;
 use sres_emulator::System;
 
 const TARGET_BUFFER_SIZE: usize = 1024;
 
+/// Average number of queued samples that dynamic rate control aims for. The queue is topped up
+/// to [TARGET_BUFFER_SIZE] once per frame and drains by about 533 samples until the next one.
+const TARGET_BUFFER_FILL: usize = TARGET_BUFFER_SIZE * 3 / 4;
+
 /// Audio output handler that manages playback of SNES APU audio samples
 pub struct AudioOutput {
     stream: Option<Stream>,
@@ -68,11 +74,16 @@ impl AudioOutput {
             .default_output_config()
             .map_err(|_| BuildStreamError::StreamConfigNotSupported)?;
 
+        // Use the native rate of the device, the APU output is resampled to match.
         let config = StreamConfig {
-            channels: 2,        // Stereo output
-            sample_rate: 32000, // SNES APU sample rate
+            channels: supported_config.channels(),
+            sample_rate: supported_config.sample_rate(),
             buffer_size: cpal::BufferSize::Default,
         };
+        info!(
+            "Audio output at {}Hz with {} channels",
+            config.sample_rate, config.channels
+        );
 
         match supported_config.sample_format() {
             SampleFormat::F32 => self.build_stream::<f32>(&device, &config),
@@ -93,18 +104,20 @@ impl AudioOutput {
         config: &StreamConfig,
     ) -> Result<Stream, BuildStreamError> {
         let buffer_queue = self.buffer_queue.clone();
+        let channels = config.channels as usize;
+        let mut resampler = Resampler::new(APU_SAMPLE_RATE, config.sample_rate);
         device.build_output_stream(
             config,
             move |data: &mut [T::Output], _: &cpal::OutputCallbackInfo| {
                 if let Ok(mut queue) = buffer_queue.lock() {
-                    // Process two samples at a time for stereo
-                    for chunk in data.chunks_exact_mut(2) {
-                        let sample = queue
-                            .next_sample()
+                    resampler.update_buffer_fill(queue.len(), TARGET_BUFFER_FILL);
+                    // The mono APU output is played on all channels
+                    for frame in data.chunks_exact_mut(channels) {
+                        let sample = resampler
+                            .next_sample(|| queue.next_sample())
                             .map(T::convert)
                             .unwrap_or_else(T::silence);
-                        chunk[0] = sample; // Left channel
-                        chunk[1] = sample; // Right channel
+                        frame.fill(sample);
                     }
                 }
             },
@@ -114,14 +127,23 @@ impl AudioOutput {
     }
 
     pub fn update(&mut self, emulator: &mut System) {
-        if self.stream.is_none() {
-            return;
-        }
+        self.queue_samples(|buffer| emulator.swap_audio_buffer(buffer));
+    }
 
+    /// Queues the samples that `fill` puts into an empty buffer for playback.
+    ///
+    /// `fill` is called even without an output stream, so the emulator's audio still reaches
+    /// its frame sink. The samples are discarded in that case.
+    pub fn queue_samples(&mut self, fill: impl FnOnce(&mut AudioBuffer)) {
         if let Ok(mut queue) = self.buffer_queue.lock() {
             let mut buffer = queue.get_recycled_buffer();
-            emulator.swap_audio_buffer(&mut buffer);
-            queue.push_buffer(buffer);
+            fill(&mut buffer);
+            if self.stream.is_some() {
+                queue.push_buffer(buffer);
+            } else {
+                buffer.clear();
+                queue.recycle_buffer(buffer);
+            }
         }
     }
 
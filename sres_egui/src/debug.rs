@@ -1,6 +1,7 @@
 mod apu;
 mod cpu;
 mod event;
+mod event_viewer;
 mod log_viewer;
 mod memory;
 mod ppu;
@@ -14,7 +15,9 @@ use eframe::CreationContext;
 use egui::Color32;
 use egui::Context;
 use egui::RichText;
+use egui::TextureHandle;
 use egui::Ui;
+use event_viewer::EventViewer;
 use log_viewer::LogViewer;
 use memory::MemoryViewer;
 use ppu::PpuDebugWindow;
@@ -22,6 +25,7 @@ use sres_emulator::common::address::AddressU16;
 use sres_emulator::common::address::AddressU24;
 use sres_emulator::common::bus::Bus;
 use sres_emulator::common::util::RingBuffer;
+use sres_emulator::components::cartridge::SnesHeader;
 use sres_emulator::debugger::BreakReason;
 use sres_emulator::ExecutionResult;
 use sres_emulator::System;
@@ -36,6 +40,7 @@ pub struct DebugUi {
     memory_viewer: MemoryViewer,
     past_emulation_times: RingBuffer<Duration, 60>,
     log_viewer: LogViewer,
+    event_viewer: EventViewer,
     selected_memory_location: InternalLink,
     pub show_profiler: bool,
     break_reason: Option<BreakReason>,
@@ -51,6 +56,7 @@ impl DebugUi {
             memory_viewer: MemoryViewer::new("CPU Bus"),
             show_profiler: false,
             log_viewer: LogViewer::new(),
+            event_viewer: EventViewer::new(),
             past_emulation_times: RingBuffer::default(),
             selected_memory_location: InternalLink::None,
             break_reason: None,
@@ -117,15 +123,22 @@ impl DebugUi {
         }
     }
 
-    pub fn modals(&mut self, ctx: &Context, emulator: &mut System) {
+    pub fn modals(
+        &mut self,
+        ctx: &Context,
+        emulator: &mut System,
+        framebuffer: &TextureHandle,
+        header: &SnesHeader,
+    ) {
         self.alert.render(ctx);
         self.ppu_debug.show(ctx, emulator);
-        self.apu_debug.show(ctx, emulator);
+        self.apu_debug.show(ctx, emulator, header);
         /* if self.show_profiler && !puffin_egui::profiler_window(ctx) {
             self.show_profiler = false;
         } */
         self.log_viewer
             .show(ctx, emulator, &mut self.selected_memory_location);
+        self.event_viewer.show(ctx, emulator, framebuffer);
 
         match self.selected_memory_location {
             InternalLink::None => (),
@@ -143,6 +156,11 @@ impl DebugUi {
             .show(ctx, |addr| emulator.cpu.bus.peek_u8(addr));
     }
 
+    /// Returns a sample selected for playback in the APU window.
+    pub fn take_sample_playback(&mut self) -> Option<Vec<i16>> {
+        self.apu_debug.take_sample_playback()
+    }
+
     pub fn right_debug_panel(&mut self, ui: &mut Ui, emulator: &System) {
         self.perf_widget(ui);
         ui.separator();
@@ -184,6 +202,9 @@ impl DebugUi {
             if ui.button("Log Viewer").clicked() {
                 self.log_viewer.toggle();
             }
+            if ui.button("Event Viewer").clicked() {
+                self.event_viewer.toggle();
+            }
         });
     }
 
@@ -1,26 +1,53 @@
+use std::path::PathBuf;
+
 use egui::Color32;
 use egui::Context;
 use egui::Stroke;
 use egui::Ui;
+use log::error;
+use log::info;
+use sres_emulator::apu::spc_file::Id666Tags;
+use sres_emulator::apu::APU_SAMPLE_RATE;
+use sres_emulator::components::cartridge::SnesHeader;
 use sres_emulator::components::s_dsp::voice::AudioRingBuffer;
 use sres_emulator::components::s_dsp::voice::GainMode;
 use sres_emulator::components::s_dsp::voice::OUTX_BUFFER_SIZE;
+use sres_emulator::components::s_dsp::BrrSample;
+use sres_emulator::components::s_dsp::VoiceCapture;
+use sres_emulator::components::s_dsp::VoiceOverrides;
 use sres_emulator::System;
 
+use crate::util::unix_timestamp;
+
 pub struct ApuDebugWindow {
     open: bool,
+    /// Samples found by all scans of the voice sample sources.
+    samples: Vec<BrrSample>,
+    /// Sample to be played by the audio output, see [Self::take_sample_playback].
+    sample_playback: Option<Vec<i16>>,
 }
 
 impl ApuDebugWindow {
     pub fn new() -> Self {
-        ApuDebugWindow { open: false }
+        ApuDebugWindow {
+            open: false,
+            samples: Vec::new(),
+            sample_playback: None,
+        }
+    }
+
+    pub fn take_sample_playback(&mut self) -> Option<Vec<i16>> {
+        self.sample_playback.take()
     }
 
     pub fn toggle(&mut self) {
         self.open = !self.open;
     }
 
-    pub fn show(&mut self, ctx: &Context, emulator: &System) {
+    pub fn show(&mut self, ctx: &Context, emulator: &mut System, header: &SnesHeader) {
+        let previous_overrides = emulator.debug().apu().dsp().voice_overrides();
+        let mut overrides = previous_overrides;
+        let mut toggle_capture = false;
         egui::Window::new("APU Debug")
             .open(&mut self.open)
             .default_width(1200.0)
@@ -29,6 +56,21 @@ impl ApuDebugWindow {
                 let debug = emulator.debug();
                 let apu_debug = debug.apu();
 
+                ui.horizontal(|ui| {
+                    if ui.button("Export SPC").clicked() {
+                        export_spc(emulator, header);
+                    }
+                    let capture_label = if apu_debug.dsp().is_capturing_voices() {
+                        "Stop Voice Capture"
+                    } else {
+                        "Capture Voices"
+                    };
+                    toggle_capture = ui
+                        .button(capture_label)
+                        .on_hover_text("Records up to 5 minutes of each voice")
+                        .clicked();
+                });
+
                 ui.heading("S-DSP Voice Status");
                 ui.separator();
 
@@ -37,7 +79,13 @@ impl ApuDebugWindow {
                     .spacing([5.0, 5.0])
                     .show(ui, |ui| {
                         for i in 0..8 {
-                            voice_detail_widget(ui, i, &apu_debug.dsp(), apu_debug.ram());
+                            voice_detail_widget(
+                                ui,
+                                i,
+                                &apu_debug.dsp(),
+                                apu_debug.ram(),
+                                &mut overrides,
+                            );
                             if i % 4 == 3 {
                                 ui.end_row();
                             }
@@ -47,15 +95,116 @@ impl ApuDebugWindow {
                 ui.separator();
                 ui.heading("Global DSP Status");
                 global_dsp_state_widget(ui, &apu_debug.dsp(), apu_debug.ram());
+
+                ui.separator();
+                egui::CollapsingHeader::new("Samples").show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        // Voices only select one sample at a time, so scans are merged to collect
+                        // all samples played over time.
+                        if ui
+                            .button("Scan Voice Samples")
+                            .on_hover_text("Adds the samples currently selected by any voice")
+                            .clicked()
+                        {
+                            for sample in apu_debug.dsp().samples(apu_debug.ram()) {
+                                self.samples.retain(|known| known.source != sample.source);
+                                self.samples.push(sample);
+                            }
+                            self.samples.sort_by_key(|sample| sample.source);
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.samples.clear();
+                        }
+                    });
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for sample in &self.samples {
+                                if let Some(playback) = sample_widget(ui, sample) {
+                                    self.sample_playback = Some(playback);
+                                }
+                            }
+                        });
+                });
             });
+
+        if overrides != previous_overrides {
+            emulator.set_dsp_voice_overrides(overrides);
+        }
+        if toggle_capture {
+            match emulator.stop_voice_capture() {
+                Some(capture) => save_voice_capture(&capture),
+                None => emulator.start_voice_capture(),
+            }
+        }
+    }
+}
+
+/// Saves the output of each voice as a timestamped .wav file in the working directory.
+fn save_voice_capture(capture: &VoiceCapture) {
+    let timestamp = unix_timestamp();
+    for voice in 0..8 {
+        let path = PathBuf::from(format!("voice{voice}-{timestamp}.wav"));
+        match capture.save_wav(voice, &path) {
+            Ok(()) => info!("Saved voice {voice} to {path:?}"),
+            Err(err) => error!("Failed to save voice {voice}: {err}"),
+        }
     }
 }
 
+/// Saves the APU state as a timestamped .spc file in the working directory.
+fn export_spc(emulator: &System, header: &SnesHeader) {
+    let path = PathBuf::from(format!("apu-{}.spc", unix_timestamp()));
+    let debug = emulator.debug();
+    let spc = debug.apu().spc_file(Some(Id666Tags::from_header(header)));
+    match spc.save(&path) {
+        Ok(()) => info!("Saved APU state to {path:?}"),
+        Err(err) => error!("Failed to save SPC file: {err}"),
+    }
+}
+
+/// Shows a sample of the sample directory. Returns the samples to play if play was clicked.
+fn sample_widget(ui: &mut Ui, sample: &BrrSample) -> Option<Vec<i16>> {
+    let mut playback = None;
+    ui.horizontal(|ui| {
+        if ui.button("▶").on_hover_text("Play").clicked() {
+            // Loops are repeated for one second.
+            playback = Some(sample.playback(APU_SAMPLE_RATE as usize));
+        }
+        if ui.button("Export WAV").clicked() {
+            let path = PathBuf::from(format!(
+                "sample{:02X}-{}.wav",
+                sample.source,
+                unix_timestamp()
+            ));
+            match sample.save_wav(&path) {
+                Ok(()) => info!("Saved sample to {path:?}"),
+                Err(err) => error!("Failed to save sample: {err}"),
+            }
+        }
+        sample_waveform_widget(ui, &sample.samples, sample.loop_start);
+        ui.label(format!(
+            "Src:${:02X} Start:${:04X} Loop:${:04X} {} samples{}",
+            sample.source,
+            sample.start_addr,
+            sample.loop_addr,
+            sample.samples.len(),
+            if sample.loop_start.is_some() {
+                " (looping)"
+            } else {
+                ""
+            }
+        ));
+    });
+    playback
+}
+
 fn voice_detail_widget(
     ui: &mut Ui,
     voice_id: usize,
     dsp: &sres_emulator::components::s_dsp::SDspDebug,
     ram: &[u8],
+    overrides: &mut VoiceOverrides,
 ) {
     ui.group(|ui| {
         ui.set_width(200.0);
@@ -82,6 +231,8 @@ fn voice_detail_widget(
                     Color32::GRAY
                 };
                 ui.colored_label(activity_color, if is_active { "●" } else { "○" });
+                mask_toggle(ui, &mut overrides.mute, voice_id, "M", "Mute");
+                mask_toggle(ui, &mut overrides.solo, voice_id, "S", "Solo");
             });
 
             // Volume bars
@@ -213,6 +364,17 @@ fn global_dsp_state_widget(
     });
 }
 
+fn mask_toggle(ui: &mut Ui, mask: &mut u8, voice_id: usize, label: &str, hover_text: &str) {
+    let bit = 1 << voice_id;
+    if ui
+        .selectable_label(*mask & bit != 0, label)
+        .on_hover_text(hover_text)
+        .clicked()
+    {
+        *mask ^= bit;
+    }
+}
+
 fn volume_bar_widget(ui: &mut Ui, level: f32, negative: bool) {
     let size = egui::Vec2::new(30.0, 6.0);
     let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
@@ -315,6 +477,50 @@ fn waveform_widget(ui: &mut Ui, buffer: &AudioRingBuffer<OUTX_BUFFER_SIZE>) {
     );
 }
 
+/// Draws the range of samples in each column and marks the loop start.
+fn sample_waveform_widget(ui: &mut Ui, samples: &[i16], loop_start: Option<usize>) {
+    let size = egui::Vec2::new(200.0, 30.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    ui.painter().rect_filled(rect, 2.0, Color32::from_gray(20));
+
+    let columns = rect.width() as usize;
+    let to_y = |sample: i16| rect.center().y - (sample as f32 / 32768.0) * (rect.height() / 2.0);
+    if !samples.is_empty() {
+        for column in 0..columns {
+            let start = column * samples.len() / columns;
+            let end = ((column + 1) * samples.len() / columns).clamp(start + 1, samples.len());
+            let column_samples = &samples[start..end];
+            let min = column_samples.iter().copied().min().unwrap_or_default();
+            let max = column_samples.iter().copied().max().unwrap_or_default();
+            let x = rect.min.x + column as f32 + 0.5;
+            ui.painter().line_segment(
+                [
+                    egui::Pos2::new(x, to_y(max)),
+                    egui::Pos2::new(x, to_y(min) + 1.0),
+                ],
+                Stroke::new(1.0, Color32::LIGHT_GREEN),
+            );
+        }
+    }
+    if let Some(loop_start) = loop_start {
+        let x = rect.min.x + loop_start as f32 / samples.len() as f32 * rect.width();
+        ui.painter().line_segment(
+            [
+                egui::Pos2::new(x, rect.min.y),
+                egui::Pos2::new(x, rect.max.y),
+            ],
+            Stroke::new(1.0, Color32::YELLOW),
+        );
+    }
+
+    ui.painter().rect_stroke(
+        rect,
+        2.0,
+        Stroke::new(1.0, Color32::WHITE),
+        egui::StrokeKind::Inside,
+    );
+}
+
 fn pitch_to_frequency(pitch: u16) -> f32 {
     // SNES pitch calculation: frequency = (pitch / 4096) * 32000 Hz
     (pitch as f32 / 4096.0) * 32000.0
@@ -0,0 +1,134 @@
+use egui::Color32;
+use egui::Context;
+use egui::Pos2;
+use egui::Rect;
+use egui::Sense;
+use egui::Stroke;
+use egui::TextureHandle;
+use egui::Ui;
+use egui::Vec2;
+use sres_emulator::main_bus::TimedEvent;
+use sres_emulator::main_bus::TimedEventKind;
+use sres_emulator::System;
+
+/// Dots per scanline and scanlines per frame shown in the event viewer.
+const DOTS: f32 = 340.0;
+const SCANLINES: f32 = 262.0;
+/// The visible picture starts at dot 22 of scanline 1.
+const PICTURE_ORIGIN: Vec2 = Vec2::new(22.0, 1.0);
+const SCALE: f32 = 2.0;
+const HOVER_DISTANCE: f32 = 4.0;
+
+pub struct EventViewer {
+    is_open: bool,
+}
+
+impl EventViewer {
+    pub fn new() -> Self {
+        Self { is_open: false }
+    }
+
+    pub fn toggle(&mut self) {
+        self.is_open = !self.is_open;
+    }
+
+    pub fn show(&mut self, ctx: &Context, emulator: &System, framebuffer: &TextureHandle) {
+        egui::Window::new("Event Viewer")
+            .open(&mut self.is_open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                legend_widget(ui);
+                ui.separator();
+                event_grid_widget(ui, emulator, framebuffer);
+            });
+        emulator.debugger().frame_events.enabled = self.is_open;
+    }
+}
+
+fn event_color(kind: &TimedEventKind) -> Color32 {
+    match kind {
+        TimedEventKind::PpuWrite(..) => Color32::LIGHT_GREEN,
+        TimedEventKind::ApuWrite(..) => Color32::LIGHT_BLUE,
+        TimedEventKind::Dma(_) => Color32::GOLD,
+        TimedEventKind::HdmaEnable(_) => Color32::from_rgb(255, 160, 64),
+        TimedEventKind::Nmi => Color32::RED,
+        TimedEventKind::Irq => Color32::from_rgb(255, 128, 255),
+    }
+}
+
+fn legend_widget(ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        for (label, kind) in [
+            ("PPU write", TimedEventKind::PpuWrite(Default::default(), 0)),
+            ("APU write", TimedEventKind::ApuWrite(Default::default(), 0)),
+            ("DMA", TimedEventKind::Dma(0)),
+            ("HDMAEN", TimedEventKind::HdmaEnable(0)),
+            ("NMI", TimedEventKind::Nmi),
+            ("IRQ", TimedEventKind::Irq),
+        ] {
+            ui.colored_label(event_color(&kind), format!("■ {label}"));
+        }
+    });
+}
+
+fn event_grid_widget(ui: &mut Ui, emulator: &System, framebuffer: &TextureHandle) {
+    let clock_info = emulator.clock_info();
+    let (response, painter) =
+        ui.allocate_painter(Vec2::new(DOTS, SCANLINES) * SCALE, Sense::hover());
+    let to_screen = |hdot: f32, v: f32| response.rect.min + Vec2::new(hdot, v) * SCALE;
+
+    painter.rect_filled(response.rect, 0.0, Color32::from_gray(32));
+    let visible_lines = (clock_info.vblank_start() - 1) as f32;
+    let picture = Rect::from_min_size(
+        to_screen(PICTURE_ORIGIN.x, PICTURE_ORIGIN.y),
+        Vec2::new(256.0, visible_lines) * SCALE,
+    );
+    painter.image(
+        framebuffer.id(),
+        picture,
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+        Color32::from_gray(160),
+    );
+
+    // Current position of the PPU
+    let position = to_screen(clock_info.hdot() as f32, clock_info.v as f32);
+    painter.hline(
+        response.rect.x_range(),
+        position.y,
+        Stroke::new(1.0, Color32::from_white_alpha(64)),
+    );
+
+    let debugger = emulator.debugger();
+    let events = debugger.frame_events.events(clock_info);
+    let event_pos = |event: &TimedEvent| {
+        to_screen(event.clock.hdot() as f32, event.clock.v as f32) + Vec2::splat(SCALE / 2.0)
+    };
+    for event in &events {
+        painter.circle_filled(event_pos(event), SCALE, event_color(&event.kind));
+    }
+
+    let Some(hover_pos) = response.hover_pos() else {
+        return;
+    };
+    let hovered = events
+        .iter()
+        .map(|event| (event_pos(event).distance(hover_pos), event))
+        .filter(|(distance, _)| *distance <= HOVER_DISTANCE)
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    if let Some((_, event)) = hovered {
+        painter.circle_stroke(
+            event_pos(event),
+            SCALE * 2.0,
+            Stroke::new(1.0, Color32::WHITE),
+        );
+        response.on_hover_ui_at_pointer(|ui| {
+            ui.label(format!("{}", event.kind));
+            ui.label(format!(
+                "V: {} H: {} (dot {})",
+                event.clock.v,
+                event.clock.h_counter,
+                event.clock.hdot()
+            ));
+        });
+    }
+}
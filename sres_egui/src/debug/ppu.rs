@@ -1,6 +1,8 @@
 use eframe::CreationContext;
+use egui::Color32;
 use egui::ColorImage;
 use egui::Context;
+use egui::Stroke;
 use egui::TextureHandle;
 use egui::TextureOptions;
 use egui::Ui;
@@ -9,8 +11,11 @@ use egui_extras::Column;
 use egui_extras::TableBuilder;
 use sres_emulator::common::clock::ClockInfo;
 use sres_emulator::components::ppu::BackgroundId;
+use sres_emulator::components::ppu::DebugLayer;
 use sres_emulator::components::ppu::PpuDebug;
+use sres_emulator::components::ppu::PpuDebugOverrides;
 use sres_emulator::components::ppu::VramRenderSelection;
+use sres_emulator::components::ppu::MODE7_PLANE_SIZE;
 use sres_emulator::System;
 
 use crate::util::EguiImageImpl;
@@ -21,6 +26,7 @@ enum PpuDebugTabs {
     Sprites,
     Vram,
     Palette,
+    Mode7,
 }
 
 pub struct PpuDebugWindow {
@@ -30,6 +36,7 @@ pub struct PpuDebugWindow {
     sprites_widget: PpuSpritesWidget,
     vram_widget: PpuVramWidget,
     palette_widget: PpuPaletteWidget,
+    mode7_widget: PpuMode7Widget,
 }
 
 impl PpuDebugWindow {
@@ -41,6 +48,7 @@ impl PpuDebugWindow {
             sprites_widget: PpuSpritesWidget::new(cc),
             vram_widget: PpuVramWidget::new(cc),
             palette_widget: PpuPaletteWidget::new(cc),
+            mode7_widget: PpuMode7Widget::new(cc),
         }
     }
 
@@ -48,11 +56,15 @@ impl PpuDebugWindow {
         self.open = !self.open;
     }
 
-    pub fn show(&mut self, ctx: &Context, emulator: &System) {
+    pub fn show(&mut self, ctx: &Context, emulator: &mut System) {
         egui::Window::new("PPU")
             .open(&mut self.open)
             .show(ctx, |ui| {
                 clock_info_widget(ui, emulator.clock_info());
+                let mut overrides = emulator.ppu_debug_overrides();
+                if debug_overrides_widget(ui, &mut overrides) {
+                    emulator.set_ppu_debug_overrides(overrides);
+                }
                 tabs_widget(
                     ui,
                     &[
@@ -60,6 +72,7 @@ impl PpuDebugWindow {
                         PpuDebugTabs::Sprites,
                         PpuDebugTabs::Vram,
                         PpuDebugTabs::Palette,
+                        PpuDebugTabs::Mode7,
                     ],
                     &mut self.selected_tab,
                 );
@@ -71,6 +84,7 @@ impl PpuDebugWindow {
                     PpuDebugTabs::Sprites => self.sprites_widget.show(ui, &emulator.debug().ppu()),
                     PpuDebugTabs::Vram => self.vram_widget.show(ui, &emulator.debug().ppu()),
                     PpuDebugTabs::Palette => self.palette_widget.show(ui, &emulator.debug().ppu()),
+                    PpuDebugTabs::Mode7 => self.mode7_widget.show(ui, &emulator.debug().ppu()),
                 }
             });
     }
@@ -80,6 +94,39 @@ pub fn clock_info_widget(ui: &mut Ui, clock_info: ClockInfo) {
     ui.label(format!("V, H: ({}, {})", clock_info.v, clock_info.hdot()));
 }
 
+/// Checkboxes to override the PPU settings of the game. Returns true if `overrides` changed.
+fn debug_overrides_widget(ui: &mut Ui, overrides: &mut PpuDebugOverrides) -> bool {
+    let previous = *overrides;
+    egui::CollapsingHeader::new("Overrides").show(ui, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Hide:");
+            for (id, hidden) in overrides.hide_bg.iter_mut().enumerate() {
+                ui.checkbox(hidden, format!("BG{}", id + 1));
+            }
+            ui.checkbox(&mut overrides.hide_obj, "OBJ");
+            ui.checkbox(&mut overrides.hide_sub_screen, "Sub screen");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Disable:");
+            ui.checkbox(&mut overrides.disable_color_math, "Color math");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Highlight:");
+            ui.selectable_value(&mut overrides.highlight, None, "None");
+            for layer in [
+                DebugLayer::Background(BackgroundId::BG1),
+                DebugLayer::Background(BackgroundId::BG2),
+                DebugLayer::Background(BackgroundId::BG3),
+                DebugLayer::Background(BackgroundId::BG4),
+                DebugLayer::Object,
+            ] {
+                ui.selectable_value(&mut overrides.highlight, Some(layer), layer.to_string());
+            }
+        });
+    });
+    *overrides != previous
+}
+
 struct PpuBackgroundWidget {
     selected_bg: BackgroundId,
     tilemap_texture: TextureHandle,
@@ -136,31 +183,6 @@ fn tilemap_widget(ui: &mut Ui, tilemap_texture: &TextureHandle) {
     ui.image((tilemap_texture.id(), Vec2::new(512.0, 512.0)));
 }
 
-#[cfg(test)]
-mod tests {
-    use sres_emulator::common::clock::ClockInfo;
-
-    use super::*;
-
-    /// All stateless PPU widgets in one combined snapshot.
-    #[test]
-    fn ppu_widgets() {
-        crate::test_utils::widget_snapshot("ppu/ppu_widgets", |ui| {
-            ui.vertical(|ui| {
-                ui.label("── clock_info_widget ──");
-                clock_info_widget(ui, ClockInfo::default()); // V=0, H=0
-                clock_info_widget(ui, ClockInfo::from_master_clock(500_000)); // mid-frame
-
-                ui.label("── tabs_widget ──");
-                let mut first = "Alpha";
-                tabs_widget(ui, &["Alpha", "Beta", "Gamma"], &mut first); // first selected
-                let mut last = "Gamma";
-                tabs_widget(ui, &["Alpha", "Beta", "Gamma"], &mut last); // last selected
-            });
-        });
-    }
-}
-
 struct PpuSpritesWidget {
     sprite_thumbnails: Vec<TextureHandle>,
 }
@@ -373,3 +395,93 @@ impl PpuPaletteWidget {
         ui.image((self.palette_texture.id(), Vec2::new(256.0, 256.0)));
     }
 }
+
+const MODE7_PLANE_DISPLAY_SIZE: f32 = 512.0;
+
+struct PpuMode7Widget {
+    plane_texture: TextureHandle,
+    selected_scanline: u32,
+}
+
+impl PpuMode7Widget {
+    pub fn new(cc: &CreationContext) -> Self {
+        PpuMode7Widget {
+            plane_texture: cc.egui_ctx.load_texture(
+                "Mode7Plane",
+                ColorImage::example(),
+                TextureOptions::NEAREST,
+            ),
+            selected_scanline: 0,
+        }
+    }
+
+    pub fn update_textures(&mut self, ppu: &PpuDebug<'_>) {
+        self.plane_texture.set(
+            ppu.render_mode7_plane::<EguiImageImpl>(),
+            TextureOptions::NEAREST,
+        );
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, ppu: &PpuDebug<'_>) {
+        self.update_textures(ppu);
+
+        let scanline_count = ppu.mode7_scanline_count();
+        self.selected_scanline = self.selected_scanline.min(scanline_count - 1);
+        ui.add(
+            egui::Slider::new(&mut self.selected_scanline, 0..=scanline_count - 1).text("Scanline"),
+        );
+        ui.label(ppu.mode7_info(self.selected_scanline));
+
+        let response = ui.image((
+            self.plane_texture.id(),
+            Vec2::splat(MODE7_PLANE_DISPLAY_SIZE),
+        ));
+
+        // Overlay the part of the plane that is visible on screen. Each scanline maps to a line
+        // on the plane, the selected scanline is highlighted.
+        let painter = ui.painter_at(response.rect);
+        let scale = MODE7_PLANE_DISPLAY_SIZE / MODE7_PLANE_SIZE as f32;
+        let to_screen =
+            |(x, y): (i32, i32)| response.rect.min + Vec2::new(x as f32, y as f32) * scale;
+        let viewport = ppu.mode7_viewport();
+        let outline = Stroke::new(1.0, Color32::YELLOW);
+        for pair in viewport.windows(2) {
+            painter.line_segment([to_screen(pair[0].0), to_screen(pair[1].0)], outline);
+            painter.line_segment([to_screen(pair[0].1), to_screen(pair[1].1)], outline);
+        }
+        if let (Some(first), Some(last)) = (viewport.first(), viewport.last()) {
+            painter.line_segment([to_screen(first.0), to_screen(first.1)], outline);
+            painter.line_segment([to_screen(last.0), to_screen(last.1)], outline);
+        }
+        let (left, right) = viewport[self.selected_scanline as usize];
+        painter.line_segment(
+            [to_screen(left), to_screen(right)],
+            Stroke::new(2.0, Color32::RED),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sres_emulator::common::clock::ClockInfo;
+
+    use super::*;
+
+    /// All stateless PPU widgets in one combined snapshot.
+    #[test]
+    fn ppu_widgets() {
+        crate::test_utils::widget_snapshot("ppu/ppu_widgets", |ui| {
+            ui.vertical(|ui| {
+                ui.label("── clock_info_widget ──");
+                clock_info_widget(ui, ClockInfo::default()); // V=0, H=0
+                clock_info_widget(ui, ClockInfo::from_master_clock(500_000)); // mid-frame
+
+                ui.label("── tabs_widget ──");
+                let mut first = "Alpha";
+                tabs_widget(ui, &["Alpha", "Beta", "Gamma"], &mut first); // first selected
+                let mut last = "Gamma";
+                tabs_widget(ui, &["Alpha", "Beta", "Gamma"], &mut last); // last selected
+            });
+        });
+    }
+}
@@ -3,9 +3,11 @@ pub mod audio;
 pub mod debug;
 pub mod embedded_roms;
 pub mod home;
+pub mod spc_player;
 #[cfg(test)]
 mod test_utils;
 pub mod util;
+pub mod video_filter;
 
 use app::EmulatorApp;
 
@@ -13,7 +15,7 @@ use app::EmulatorApp;
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(argh::FromArgs)]
 struct ResArgs {
-    /// rom file to load
+    /// rom or .spc file to load
     #[argh(positional)]
     rom: Option<String>,
 
@@ -27,7 +29,6 @@ fn main() {
     use egui::vec2;
     use egui::ViewportBuilder;
     use sres_emulator::common::logging;
-    use sres_emulator::components::cartridge::Cartridge;
     use tracing_chrome::ChromeLayerBuilder;
     use tracing_subscriber::prelude::*;
 
@@ -46,15 +47,12 @@ fn main() {
         ..Default::default()
     };
 
-    let cartridge = args.rom.map(|path| {
-        let path = std::path::PathBuf::from(path);
-        Cartridge::with_sfc_file(&path).unwrap()
-    });
+    let rom_path = args.rom.map(std::path::PathBuf::from);
 
     eframe::run_native(
         "Super Rust Entertainment System",
         native_options,
-        Box::new(|cc| Ok(Box::new(EmulatorApp::new(cc, cartridge)))),
+        Box::new(|cc| Ok(Box::new(EmulatorApp::new(cc, rom_path)))),
     )
     .unwrap();
 }
@@ -0,0 +1,123 @@
+//! Plays .spc files on a standalone APU.
+use std::path::Path;
+
+use anyhow::Result;
+use egui::Context;
+use egui::FontId;
+use egui::RichText;
+use egui::Ui;
+use sres_emulator::apu::spc_file::Id666Tags;
+use sres_emulator::apu::spc_file::SpcFile;
+use sres_emulator::apu::Apu;
+use sres_emulator::apu::APU_SAMPLE_RATE;
+use sres_emulator::debugger::Debugger;
+
+use crate::audio::AudioOutput;
+
+pub struct SpcPlayer {
+    apu: Apu,
+    name: String,
+    tags: Option<Id666Tags>,
+    samples_played: u64,
+    paused: bool,
+}
+
+impl SpcPlayer {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(
+            &SpcFile::load(path)?,
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+        ))
+    }
+
+    pub fn with_spc_data(data: &[u8], name: String) -> Result<Self> {
+        Ok(Self::new(&SpcFile::parse(data)?, name))
+    }
+
+    fn new(spc: &SpcFile, name: String) -> Self {
+        let mut apu = Apu::new(Debugger::new());
+        apu.load_spc_file(spc);
+        Self {
+            apu,
+            name,
+            tags: spc.tags.clone(),
+            samples_played: 0,
+            paused: false,
+        }
+    }
+
+    /// Generates enough samples to keep the audio output busy.
+    pub fn update(&mut self, audio_output: &mut AudioOutput) {
+        if self.paused {
+            return;
+        }
+        let count = audio_output.samples_needed_to_maintain_buffer();
+        self.apu.execute_for_audio_samples(count);
+        self.samples_played += count as u64;
+        audio_output.queue_samples(|buffer| self.apu.swap_audio_buffer(buffer));
+    }
+
+    /// Shows the song metadata and playback controls. Returns false when the player is closed.
+    pub fn ui(&mut self, ctx: &Context) -> bool {
+        let mut open = true;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let title = match &self.tags {
+                Some(tags) if !tags.song_title.is_empty() => tags.song_title.clone(),
+                _ => self.name.clone(),
+            };
+            ui.label(RichText::new(title).font(FontId::proportional(32.0)));
+            if let Some(tags) = &self.tags {
+                tags_grid(ui, tags);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label(self.progress());
+                if ui
+                    .button(if self.paused { "Play" } else { "Pause" })
+                    .clicked()
+                {
+                    self.paused = !self.paused;
+                }
+                if ui.button("Stop").clicked() {
+                    open = false;
+                }
+            });
+        });
+        open
+    }
+
+    fn progress(&self) -> String {
+        let elapsed = format_seconds(self.samples_played / APU_SAMPLE_RATE as u64);
+        match &self.tags {
+            Some(tags) if tags.play_seconds > 0 => {
+                format!("{elapsed} / {}", format_seconds(tags.play_seconds as u64))
+            }
+            _ => elapsed,
+        }
+    }
+}
+
+fn tags_grid(ui: &mut Ui, tags: &Id666Tags) {
+    egui::Grid::new("spc_tags").num_columns(2).show(ui, |ui| {
+        for (label, value) in [
+            ("Game", &tags.game_title),
+            ("Artist", &tags.artist),
+            ("Dumper", &tags.dumper),
+            ("Comments", &tags.comments),
+        ] {
+            ui.label(label);
+            ui.label(value);
+            ui.end_row();
+        }
+        ui.label("Fade");
+        ui.label(format!("{}ms", tags.fade_millis));
+        ui.end_row();
+    });
+}
+
+fn format_seconds(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
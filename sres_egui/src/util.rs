@@ -8,6 +8,10 @@ use std::ops::Sub;
 use std::ops::SubAssign;
 use std::sync::Arc;
 use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::SystemTime;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::UNIX_EPOCH;
 
 use egui::Color32;
 use egui::ColorImage;
@@ -158,3 +162,17 @@ impl SubAssign<Duration> for Instant {
         *self = *self - other;
     }
 }
+
+/// Seconds since the unix epoch, used to give exported files unique names.
+/// `SystemTime::now` panics on wasm32, so this uses `Date.now` there.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+#[cfg(target_arch = "wasm32")]
+pub fn unix_timestamp() -> u64 {
+    (date_now() / 1000.0) as u64
+}
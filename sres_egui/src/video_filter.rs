@@ -0,0 +1,307 @@
+//! Filters to upscale the emulator output, implemented on the CPU.
+//!
+//! All filters first stretch the frame to square pixels (see [Framebuffer::aspect_scale]) and
+//! then scale it up by [FILTER_SCALE].
+use std::f32::consts::PI;
+
+use egui::TextureOptions;
+use sres_emulator::common::image::Image;
+use sres_emulator::common::image::Rgb15;
+use sres_emulator::common::image::Rgba32;
+use sres_emulator::components::ppu::Framebuffer;
+
+pub const FILTER_SCALE: u32 = 2;
+
+#[derive(Default, Debug, PartialEq, Eq, Copy, Clone, strum::Display, strum::VariantArray)]
+pub enum VideoFilter {
+    #[default]
+    Nearest,
+    Scale2x,
+    Scanlines,
+    Ntsc,
+}
+
+impl VideoFilter {
+    pub fn apply<ImageT: Image>(self, frame: &Framebuffer) -> ImageT {
+        let frame = SquarePixels::new(frame);
+        match self {
+            VideoFilter::Nearest => nearest(&frame),
+            VideoFilter::Scale2x => scale2x(&frame),
+            VideoFilter::Scanlines => scanlines(&frame),
+            VideoFilter::Ntsc => ntsc(&frame),
+        }
+    }
+
+    /// Texture sampling to use when stretching the filtered image to the screen.
+    pub fn texture_options(self) -> TextureOptions {
+        match self {
+            VideoFilter::Nearest | VideoFilter::Scale2x => TextureOptions::NEAREST,
+            VideoFilter::Scanlines | VideoFilter::Ntsc => TextureOptions::LINEAR,
+        }
+    }
+}
+
+/// Frame stretched to square pixels.
+struct SquarePixels<'a> {
+    frame: &'a Framebuffer,
+    x_scale: u32,
+    y_scale: u32,
+    width: u32,
+    height: u32,
+}
+
+impl<'a> SquarePixels<'a> {
+    fn new(frame: &'a Framebuffer) -> Self {
+        let (x_scale, y_scale) = frame.aspect_scale();
+        Self {
+            frame,
+            x_scale,
+            y_scale,
+            width: frame.width() * x_scale,
+            height: frame.height() * y_scale,
+        }
+    }
+
+    /// Returns the pixel at (x, y), coordinates outside of the frame are clamped to the edge.
+    fn pixel(&self, x: i64, y: i64) -> Rgb15 {
+        let x = x.clamp(0, self.width as i64 - 1) as u32;
+        let y = y.clamp(0, self.height as i64 - 1) as u32;
+        self.frame[(x / self.x_scale, y / self.y_scale)]
+    }
+}
+
+fn nearest<ImageT: Image>(frame: &SquarePixels) -> ImageT {
+    let mut image = ImageT::new(frame.width * FILTER_SCALE, frame.height * FILTER_SCALE);
+    for y in 0..frame.height * FILTER_SCALE {
+        for x in 0..frame.width * FILTER_SCALE {
+            let pixel = frame.pixel((x / FILTER_SCALE) as i64, (y / FILTER_SCALE) as i64);
+            image.set_pixel((x, y), pixel.into());
+        }
+    }
+    image
+}
+
+/// Scale2x (aka EPX) smooths diagonal edges without blurring.
+fn scale2x<ImageT: Image>(frame: &SquarePixels) -> ImageT {
+    let mut image = ImageT::new(frame.width * 2, frame.height * 2);
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let (xi, yi) = (x as i64, y as i64);
+            let center = frame.pixel(xi, yi);
+            let up = frame.pixel(xi, yi - 1);
+            let left = frame.pixel(xi - 1, yi);
+            let right = frame.pixel(xi + 1, yi);
+            let down = frame.pixel(xi, yi + 1);
+
+            let pick = |a: Rgb15, b: Rgb15, c: Rgb15, d: Rgb15| {
+                if a == b && a != c && b != d {
+                    a
+                } else {
+                    center
+                }
+            };
+            let (x, y) = (x * 2, y * 2);
+            image.set_pixel((x, y), pick(left, up, down, right).into());
+            image.set_pixel((x + 1, y), pick(up, right, left, down).into());
+            image.set_pixel((x, y + 1), pick(down, left, right, up).into());
+            image.set_pixel((x + 1, y + 1), pick(right, down, up, left).into());
+        }
+    }
+    image
+}
+
+/// Brightness of the dark line between two scanlines.
+const SCANLINE_BRIGHTNESS: f32 = 0.5;
+
+fn scanlines<ImageT: Image>(frame: &SquarePixels) -> ImageT {
+    let mut image = ImageT::new(frame.width * FILTER_SCALE, frame.height * FILTER_SCALE);
+    for y in 0..frame.height * FILTER_SCALE {
+        let brightness = if y % FILTER_SCALE == FILTER_SCALE - 1 {
+            SCANLINE_BRIGHTNESS
+        } else {
+            1.0
+        };
+        for x in 0..frame.width * FILTER_SCALE {
+            let pixel = frame.pixel((x / FILTER_SCALE) as i64, (y / FILTER_SCALE) as i64);
+            image.set_pixel((x, y), to_rgba(to_rgb(pixel).map(|c| c * brightness)));
+        }
+    }
+    image
+}
+
+/// Simulates the artifacts of a composite video signal, in the spirit of blargg's snes_ntsc.
+///
+/// Each line is encoded into a composite signal where the chroma is modulated onto a color
+/// subcarrier that completes a cycle every 1.5 low resolution pixels, with the phase shifting
+/// from line to line. Decoding separates luma and chroma by averaging over a subcarrier cycle,
+/// which results in the typical color bleeding and fringes at sharp edges.
+fn ntsc<ImageT: Image>(frame: &SquarePixels) -> ImageT {
+    let width = frame.width * FILTER_SCALE;
+    // A subcarrier cycle spans 1.5 low resolution pixels.
+    let samples_per_pixel = (FILTER_SCALE * (frame.width / 256).max(1)) as usize;
+    let samples_per_cycle = samples_per_pixel * 3 / 2;
+    let phase_step = 2.0 * PI / samples_per_cycle as f32;
+
+    let mut image = ImageT::new(width, frame.height * FILTER_SCALE);
+    let mut signal = vec![0.0; width as usize];
+    for y in 0..frame.height {
+        let line_phase = (y % 3) as f32 * 2.0 * PI / 3.0;
+        let carrier: Vec<(f32, f32)> = (0..samples_per_cycle)
+            .map(|k| (line_phase + k as f32 * phase_step).sin_cos())
+            .collect();
+        let carrier_at = |x: usize| carrier[x % samples_per_cycle];
+
+        for (x, sample) in signal.iter_mut().enumerate() {
+            let pixel = frame.pixel((x as u32 / FILTER_SCALE) as i64, y as i64);
+            let [luma, i, q] = rgb_to_yiq(to_rgb(pixel));
+            let (sin, cos) = carrier_at(x);
+            *sample = luma + i * cos + q * sin;
+        }
+
+        for x in 0..width as usize {
+            let window = |length: usize| {
+                let start = x.saturating_sub(length / 2);
+                let end = (start + length).min(signal.len());
+                start..end
+            };
+            let luma_window = window(samples_per_cycle);
+            let luma = signal[luma_window.clone()].iter().sum::<f32>() / luma_window.len() as f32;
+
+            let chroma_window = window(samples_per_cycle * 2);
+            let (mut i, mut q) = (0.0, 0.0);
+            for k in chroma_window.clone() {
+                let chroma = signal[k] - luma;
+                let (sin, cos) = carrier_at(k);
+                i += chroma * cos;
+                q += chroma * sin;
+            }
+            // Demodulation yields half the amplitude.
+            let scale = 2.0 / chroma_window.len() as f32;
+            let rgb = yiq_to_rgb([luma, i * scale, q * scale]);
+
+            for dy in 0..FILTER_SCALE {
+                let brightness = if dy == FILTER_SCALE - 1 { 0.75 } else { 1.0 };
+                image.set_pixel(
+                    (x as u32, y * FILTER_SCALE + dy),
+                    to_rgba(rgb.map(|c| c * brightness)),
+                );
+            }
+        }
+    }
+    image
+}
+
+fn to_rgb(pixel: Rgb15) -> [f32; 3] {
+    let Rgba32([r, g, b, _]) = pixel.into();
+    [r, g, b].map(|c| c as f32 / 255.0)
+}
+
+fn to_rgba(rgb: [f32; 3]) -> Rgba32 {
+    let [r, g, b] = rgb.map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8);
+    Rgba32([r, g, b, 255])
+}
+
+fn rgb_to_yiq([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        0.596 * r - 0.274 * g - 0.322 * b,
+        0.211 * r - 0.523 * g + 0.312 * b,
+    ]
+}
+
+fn yiq_to_rgb([y, i, q]: [f32; 3]) -> [f32; 3] {
+    [
+        y + 0.956 * i + 0.621 * q,
+        y - 0.272 * i - 0.647 * q,
+        y - 1.106 * i + 1.703 * q,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use image::RgbaImage;
+    use sres_emulator::common::test_util::compare_image_against_golden;
+    use strum::VariantArray;
+
+    use super::*;
+
+    /// Frame with color bars, a checkerboard and a diagonal line to show the filter effects.
+    fn test_frame(width: u32, height: u32) -> Framebuffer {
+        let colors = [
+            0x7FFF, 0x03FF, 0x7FE0, 0x03E0, 0x7C1F, 0x001F, 0x7C00, 0x0000,
+        ];
+        let mut frame = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                frame[(x, y)] = Rgb15(if y < height / 2 {
+                    colors[(x * 8 / width) as usize]
+                } else if x == y - height / 2 {
+                    0x7FFF
+                } else if x > width / 2 && (x / 4 + y / 4) % 2 == 0 {
+                    0x294A
+                } else {
+                    0x0000
+                });
+            }
+        }
+        frame
+    }
+
+    fn compare_to_golden(image: &RgbaImage, name: &str) {
+        let path_prefix = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/video_filters")
+            .join(name);
+        compare_image_against_golden(image, &path_prefix);
+    }
+
+    #[test]
+    fn test_video_filters() {
+        let frame = test_frame(64, 48);
+        for filter in VideoFilter::VARIANTS {
+            let image: RgbaImage = filter.apply(&frame);
+            assert_eq!(image.dimensions(), (128, 96));
+            compare_to_golden(&image, &filter.to_string().to_lowercase());
+        }
+    }
+
+    #[test]
+    fn test_hires_aspect() {
+        let frame = test_frame(512, 224);
+        for filter in VideoFilter::VARIANTS {
+            let image: RgbaImage = filter.apply(&frame);
+            assert_eq!(image.dimensions(), (1024, 896));
+        }
+    }
+
+    #[test]
+    fn test_scale2x_smooths_diagonals() {
+        let mut frame = Framebuffer::new(256, 224);
+        frame[(1, 0)] = Rgb15(0x7FFF);
+        frame[(0, 1)] = Rgb15(0x7FFF);
+        let image: RgbaImage = VideoFilter::Scale2x.apply(&frame);
+        let white = image::Rgba(Rgba32::from(Rgb15(0x7FFF)).0);
+        let black = image::Rgba([0, 0, 0, 255]);
+        // The black pixel at (0, 0) gets its bottom right corner filled in.
+        assert_eq!(image[(0, 0)], black);
+        assert_eq!(image[(1, 1)], white);
+        assert_eq!(image[(2, 0)], white);
+    }
+
+    #[test]
+    fn test_ntsc_preserves_flat_colors() {
+        let mut frame = Framebuffer::new(256, 224);
+        for y in 0..224 {
+            for x in 0..256 {
+                frame[(x, y)] = Rgb15(0x4210);
+            }
+        }
+        let image: RgbaImage = VideoFilter::Ntsc.apply(&frame);
+        let Rgba32(expected) = Rgb15(0x4210).into();
+        let actual = image[(256, 100)].0;
+        for c in 0..3 {
+            assert!((expected[c] as i32 - actual[c] as i32).abs() <= 2);
+        }
+    }
+}
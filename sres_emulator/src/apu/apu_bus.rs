@@ -2,9 +2,11 @@ use intbits::Bits;
 use log::trace;
 
 use super::timers::ApuTimers;
+use super::AudioBuffer;
 use crate::common::address::AddressU16;
 use crate::common::bus::Bus;
 use crate::common::debug_events::DebugEventCollectorRef;
+use crate::components::s_dsp::DspTiming;
 use crate::components::s_dsp::SDsp;
 use crate::components::spc700::Spc700Bus;
 
@@ -25,6 +27,8 @@ pub struct ApuBus {
     pub dsp_register_select: u8,
     pub dsp_register_readonly: bool,
     pub dsp: SDsp,
+    /// Samples generated by the DSP when it is stepped with each SPC700 cycle.
+    pub dsp_samples: AudioBuffer,
     pub control: ApuControlRegister,
 }
 
@@ -42,6 +46,7 @@ impl ApuBus {
             dsp_register_readonly: false,
             dsp_register_select: 0,
             dsp: Default::default(),
+            dsp_samples: AudioBuffer::new(),
             control: ApuControlRegister::default(),
         }
     }
@@ -63,6 +68,46 @@ impl ApuBus {
             self.channel_in[3] = 0;
         }
     }
+
+    /// Restores the I/O registers at $F0-$FF from a snapshot of the RAM, e.g. from an .spc file.
+    pub fn load_io_registers(&mut self, io: &[u8]) {
+        // Not using write_control, which would clear the ports.
+        self.control.0 = io[0x1];
+        self.timers.update_timer_enable_flags(io[0x1]);
+        self.dsp_register_readonly = io[0x2].bit(7);
+        self.dsp_register_select = io[0x2].bits(0..=6);
+        self.channel_in.copy_from_slice(&io[0x4..0x8]);
+        for timer_id in 0..3 {
+            self.timers.write_target(timer_id, io[0xA + timer_id]);
+            self.timers.set_output(timer_id, io[0xD + timer_id]);
+        }
+    }
+
+    /// Returns the state of the I/O registers at $F0-$FF, e.g. to save an .spc file. This
+    /// includes the write-only timer targets, which [Self::peek_u8] does not return.
+    pub fn io_registers(&self) -> [u8; 16] {
+        let mut io = [0; 16];
+        io.copy_from_slice(&self.ram[0xF0..0x100]);
+        io[0x1] = self.control.0;
+        io[0x2] = self.dsp_register_select | (self.dsp_register_readonly as u8) << 7;
+        io[0x3] = self.dsp.read_register(self.dsp_register_select);
+        io[0x4..0x8].copy_from_slice(&self.channel_in);
+        for timer_id in 0..3 {
+            io[0xA + timer_id] = self.timers.peek_target(timer_id);
+            io[0xD + timer_id] = self.timers.peek_output(timer_id);
+        }
+        io
+    }
+
+    /// Advances timers and, with [DspTiming::Cycle], the DSP by 1 SPC cycle.
+    fn update_cycle(&mut self) {
+        self.timers.update(1);
+        if self.dsp.timing() == DspTiming::Cycle {
+            if let Some(sample) = self.dsp.step_cycle(&self.ram) {
+                self.dsp_samples.push_sample(sample);
+            }
+        }
+    }
 }
 
 impl Bus<AddressU16> for ApuBus {
@@ -88,8 +133,7 @@ impl Bus<AddressU16> for ApuBus {
     fn cycle_io(&mut self) {
         trace!("{:08} [SPC] io", self.master_clock);
         self.spc_cycle += 2;
-        // Update timers with 1 SPC cycle
-        self.timers.update(1);
+        self.update_cycle();
     }
 
     fn cycle_read_u8(&mut self, addr: AddressU16) -> u8 {
@@ -108,8 +152,7 @@ impl Bus<AddressU16> for ApuBus {
         self.debug_event_collector
             .on_event(ApuBusEvent::Read(addr, value));
 
-        // Update timers with 1 SPC cycle
-        self.timers.update(1);
+        self.update_cycle();
 
         value
     }
@@ -128,10 +171,9 @@ impl Bus<AddressU16> for ApuBus {
                 self.dsp_register_select = value.bits(0..=6);
             }
             0x00F3 => {
-                if self.dsp_register_readonly {
-                    return;
+                if !self.dsp_register_readonly {
+                    self.dsp.write_register(self.dsp_register_select, value);
                 }
-                self.dsp.write_register(self.dsp_register_select, value);
             }
             0x00F4..=0x00F7 => self.channel_out[addr.0 as usize - 0x00F4] = value,
             0x00FA..=0x00FC => {
@@ -142,8 +184,7 @@ impl Bus<AddressU16> for ApuBus {
             _ => self.ram[addr.0 as usize] = value,
         }
 
-        // Update timers with 1 SPC cycle
-        self.timers.update(1);
+        self.update_cycle();
     }
 
     fn reset(&mut self) {
@@ -1,5 +1,7 @@
 //! Dummy implementation of the audio processing unit.
 mod apu_bus;
+pub mod resampler;
+pub mod spc_file;
 mod test;
 mod timers;
 
@@ -8,11 +10,16 @@ use log::error;
 
 use self::apu_bus::ApuBus;
 pub use self::apu_bus::ApuBusEvent;
+use self::spc_file::Id666Tags;
+use self::spc_file::SpcFile;
 use crate::common::address::AddressU24;
 use crate::common::bus::BusDeviceU24;
 use crate::common::clock::ClockInfo;
 use crate::common::debug_events::DebugEventCollectorRef;
+use crate::components::s_dsp::DspTiming;
 use crate::components::s_dsp::SDspDebug;
+use crate::components::s_dsp::VoiceCapture;
+use crate::components::s_dsp::VoiceOverrides;
 use crate::components::spc700::Spc700;
 use crate::debugger::DebuggerRef;
 
@@ -66,6 +73,43 @@ impl Apu {
         self.sample_buffer.len()
     }
 
+    pub fn set_dsp_timing(&mut self, timing: DspTiming) {
+        self.spc700.bus.dsp.set_timing(timing);
+    }
+
+    pub fn set_voice_overrides(&mut self, overrides: VoiceOverrides) {
+        self.spc700.bus.dsp.set_voice_overrides(overrides);
+    }
+
+    pub fn start_voice_capture(&mut self) {
+        self.spc700.bus.dsp.start_voice_capture();
+    }
+
+    pub fn stop_voice_capture(&mut self) -> Option<VoiceCapture> {
+        self.spc700.bus.dsp.stop_voice_capture()
+    }
+
+    /// Restores the state of the SPC700, RAM and DSP from an .spc file.
+    pub fn load_spc_file(&mut self, spc: &SpcFile) {
+        let bus = &mut self.spc700.bus;
+        bus.ram.copy_from_slice(&spc.ram);
+        bus.load_io_registers(&spc.ram[0xF0..0x100]);
+        if bus.control.ipl_rom_enabled() {
+            bus.ram[0xFFC0..].copy_from_slice(&spc.extra_ram);
+        }
+        bus.dsp.load_registers(&spc.dsp_registers);
+        self.spc700.set_registers(spc.registers);
+    }
+
+    /// Runs the APU on its own without a main CPU, e.g. to play an .spc file, for the duration
+    /// of `count` audio samples.
+    pub fn execute_for_audio_samples(&mut self, count: usize) {
+        for _ in 0..count {
+            let master_clock = self.spc700.bus.master_clock + CYCLES_PER_SAMPLE;
+            self.update_clock(ClockInfo::from_master_clock(master_clock));
+        }
+    }
+
     // Generate a single audio sample
     pub fn generate_sample(&mut self) -> i16 {
         let memory = &self.spc700.bus.ram;
@@ -92,25 +136,40 @@ impl Apu {
     }
 
     fn update_clock(&mut self, new_clock: ClockInfo) {
+        if self.spc700.bus.dsp.timing() == DspTiming::Cycle {
+            // The DSP is stepped by the bus, collect the samples it generated.
+            self.spc700.catch_up_to_master_clock(new_clock.master_clock);
+            for i in 0..self.spc700.bus.dsp_samples.len() {
+                self.push_sample(self.spc700.bus.dsp_samples[i]);
+            }
+            self.spc700.bus.dsp_samples.clear();
+            self.last_sample_cycle = new_clock.master_clock;
+            return;
+        }
+
         while new_clock.master_clock - self.last_sample_cycle >= CYCLES_PER_SAMPLE {
             self.last_sample_cycle += CYCLES_PER_SAMPLE;
             self.spc700.catch_up_to_master_clock(new_clock.master_clock);
 
             let sample = self.generate_sample();
-
-            // Add sample to buffer, dropping oldest samples if buffer gets too large
-            if self.sample_buffer.len() >= MAX_AUDIO_BUFFER_SIZE {
-                error!("APU audio buffer overflow - dropping samples");
-                self.sample_buffer.clear();
-            }
-            self.sample_buffer.push_sample(sample);
+            self.push_sample(sample);
         }
         self.spc700.catch_up_to_master_clock(new_clock.master_clock);
     }
 
+    fn push_sample(&mut self, sample: i16) {
+        // Add sample to buffer, dropping oldest samples if buffer gets too large
+        if self.sample_buffer.len() >= MAX_AUDIO_BUFFER_SIZE {
+            error!("APU audio buffer overflow - dropping samples");
+            self.sample_buffer.clear();
+        }
+        self.sample_buffer.push_sample(sample);
+    }
+
     fn reset(&mut self) {
         self.last_sample_cycle = 0;
         self.sample_buffer.clear();
+        self.spc700.bus.dsp_samples.clear();
     }
 }
 
@@ -148,6 +207,27 @@ impl<'a> ApuDebug<'a> {
     pub fn ram(&self) -> &[u8] {
         &self.0.spc700.bus.ram
     }
+
+    /// Captures the current state as an .spc file, which can be played without the ROM.
+    ///
+    /// KON is exported with the last value written by the program, so the voices set in it are
+    /// keyed on again when the file is loaded.
+    pub fn spc_file(&self, tags: Option<Id666Tags>) -> SpcFile {
+        let bus = &self.0.spc700.bus;
+        let mut ram = bus.ram.to_vec();
+        ram[0xF0..0x100].copy_from_slice(&bus.io_registers());
+        let mut dsp_registers = [0; 128];
+        for (reg, value) in dsp_registers.iter_mut().enumerate() {
+            *value = bus.dsp.read_register(reg as u8);
+        }
+        SpcFile {
+            registers: self.0.spc700.registers(),
+            tags,
+            ram,
+            dsp_registers,
+            extra_ram: bus.ram[0xFFC0..].try_into().unwrap(),
+        }
+    }
 }
 
 /// A typed wrapper around Vec<i16> for audio samples with proper capacity management
@@ -0,0 +1,127 @@
+//! Conversion of the 32 kHz APU output to the sample rate of the host audio device.
+
+/// Maximum deviation from the nominal ratio applied by dynamic rate control.
+pub const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+
+/// Cubic (Catmull-Rom) resampler with dynamic rate control.
+///
+/// The emulator and the audio device run on different clocks, so the queue of samples between
+/// them slowly drains or overflows. Dynamic rate control nudges the resampling ratio by up to
+/// [MAX_RATE_ADJUSTMENT] to keep the queue at its target fill level, which is inaudible.
+pub struct Resampler {
+    /// Input samples consumed per output sample at the nominal rates.
+    ratio: f64,
+    adjustment: f64,
+    /// Position between history[1] and history[2], new input is needed when it reaches 1.
+    position: f64,
+    history: [f64; 4],
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            ratio: input_rate as f64 / output_rate as f64,
+            adjustment: 0.0,
+            position: 1.0,
+            history: [0.0; 4],
+        }
+    }
+
+    /// Input samples consumed per output sample, including the rate adjustment.
+    pub fn step(&self) -> f64 {
+        self.ratio * (1.0 + self.adjustment)
+    }
+
+    /// Adjusts the rate based on the number of queued input samples. A queue above `target`
+    /// is consumed faster, a queue below is consumed slower.
+    pub fn update_buffer_fill(&mut self, fill: usize, target: usize) {
+        let deviation = (fill as f64 - target as f64) / target.max(1) as f64;
+        self.adjustment =
+            (deviation * MAX_RATE_ADJUSTMENT).clamp(-MAX_RATE_ADJUSTMENT, MAX_RATE_ADJUSTMENT);
+    }
+
+    /// Produces the next output sample, pulling new input samples from `input` as needed.
+    /// Returns None if `input` runs out, the missing input is requested again on the next call.
+    pub fn next_sample(&mut self, mut input: impl FnMut() -> Option<i16>) -> Option<i16> {
+        while self.position >= 1.0 {
+            let sample = input()?;
+            self.history.rotate_left(1);
+            self.history[3] = sample as f64;
+            self.position -= 1.0;
+        }
+        let sample = catmull_rom(self.history, self.position);
+        self.position += self.step();
+        Some(sample.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16)
+    }
+}
+
+fn catmull_rom([y0, y1, y2, y3]: [f64; 4], t: f64) -> f64 {
+    y1 + 0.5
+        * t
+        * (y2 - y0 + t * (2.0 * y0 - 5.0 * y1 + 4.0 * y2 - y3 + t * (3.0 * (y1 - y2) + y3 - y0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    fn resample(resampler: &mut Resampler, input: &[i16]) -> Vec<i16> {
+        let mut input = input.iter().copied();
+        std::iter::from_fn(|| resampler.next_sample(|| input.next())).collect()
+    }
+
+    fn sine(sample_rate: f64, index: f64) -> f64 {
+        (2.0 * PI * 440.0 * index / sample_rate).sin() * 10000.0
+    }
+
+    #[test]
+    fn test_same_rate_is_delayed_input() {
+        let input: Vec<i16> = (0..100).map(|i| i * 100).collect();
+        let output = resample(&mut Resampler::new(32000, 32000), &input);
+        assert_eq!(output.len(), 100);
+        assert_eq!(output[2..], input[..98]);
+    }
+
+    #[test]
+    fn test_resample_sine() {
+        for output_rate in [44100, 48000, 22050] {
+            let input: Vec<i16> = (0..32000).map(|i| sine(32000.0, i as f64) as i16).collect();
+            let output = resample(&mut Resampler::new(32000, output_rate), &input);
+            let expected_len = 32000 * output_rate as usize / 32000;
+            assert!(output.len().abs_diff(expected_len) <= 3);
+
+            // The output is delayed by 2 input samples.
+            let delay = 2.0 * output_rate as f64 / 32000.0;
+            for (i, sample) in output.iter().enumerate().skip(10) {
+                let expected = sine(output_rate as f64, i as f64 - delay);
+                assert!(
+                    (*sample as f64 - expected).abs() < 20.0,
+                    "{output_rate}Hz sample {i}: {sample} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_dynamic_rate_control() {
+        let mut resampler = Resampler::new(32000, 48000);
+        let nominal = resampler.step();
+        resampler.update_buffer_fill(1024, 1024);
+        assert_eq!(resampler.step(), nominal);
+
+        // Consume faster when the queue is too full, limited to 0.5%.
+        resampler.update_buffer_fill(1536, 1024);
+        assert!((resampler.step() / nominal - 1.0025).abs() < 1e-9);
+        resampler.update_buffer_fill(100000, 1024);
+        assert!((resampler.step() / nominal - 1.005).abs() < 1e-9);
+
+        // And slower when it runs empty.
+        resampler.update_buffer_fill(0, 1024);
+        assert!((resampler.step() / nominal - 0.995).abs() < 1e-9);
+        let output = resample(&mut resampler, &[0; 32000]);
+        let expected_len = (32000.0 / (nominal * 0.995)) as usize;
+        assert!(output.len().abs_diff(expected_len) <= 3);
+    }
+}
@@ -0,0 +1,270 @@
+//! Parsing of .spc files, which contain a snapshot of the APU state.
+use std::path::Path;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::components::cartridge::SnesHeader;
+use crate::components::spc700::Spc700Registers;
+
+const SIGNATURE: &[u8] = b"SNES-SPC700 Sound File Data v0.30";
+const FILE_SIZE: usize = 0x10200;
+const RAM_OFFSET: usize = 0x100;
+const DSP_OFFSET: usize = 0x10100;
+const EXTRA_RAM_OFFSET: usize = 0x101C0;
+
+pub struct SpcFile {
+    pub registers: Spc700Registers,
+    pub tags: Option<Id666Tags>,
+    /// The 64 KiB of audio RAM, including the I/O registers at $F0-$FF.
+    pub ram: Vec<u8>,
+    pub dsp_registers: [u8; 128],
+    /// RAM hidden below the IPL ROM at $FFC0-$FFFF.
+    pub extra_ram: [u8; 64],
+}
+
+/// Metadata of the song stored in an .spc file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Id666Tags {
+    pub song_title: String,
+    pub game_title: String,
+    pub dumper: String,
+    pub comments: String,
+    pub artist: String,
+    /// Length of the song before fading out.
+    pub play_seconds: u32,
+    /// Length of the fade out.
+    pub fade_millis: u32,
+}
+
+impl SpcFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(SIGNATURE) {
+            bail!("Not an SPC file");
+        }
+        if data.len() < FILE_SIZE {
+            bail!("SPC file is truncated ({} bytes)", data.len());
+        }
+        Ok(Self {
+            registers: Spc700Registers {
+                pc: u16::from_le_bytes([data[0x25], data[0x26]]),
+                a: data[0x27],
+                x: data[0x28],
+                y: data[0x29],
+                psw: data[0x2A],
+                sp: data[0x2B],
+            },
+            tags: (data[0x23] == 0x1A).then(|| Id666Tags::parse(data)),
+            ram: data[RAM_OFFSET..RAM_OFFSET + 0x10000].to_vec(),
+            dsp_registers: data[DSP_OFFSET..DSP_OFFSET + 128].try_into()?,
+            extra_ram: data[EXTRA_RAM_OFFSET..EXTRA_RAM_OFFSET + 64].try_into()?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0; FILE_SIZE];
+        data[..SIGNATURE.len()].copy_from_slice(SIGNATURE);
+        data[0x21] = 0x1A;
+        data[0x22] = 0x1A;
+        data[0x23] = if self.tags.is_some() { 0x1A } else { 0x1B };
+        data[0x24] = 30;
+        data[0x25..0x27].copy_from_slice(&self.registers.pc.to_le_bytes());
+        data[0x27] = self.registers.a;
+        data[0x28] = self.registers.x;
+        data[0x29] = self.registers.y;
+        data[0x2A] = self.registers.psw;
+        data[0x2B] = self.registers.sp;
+        if let Some(tags) = &self.tags {
+            tags.write(&mut data);
+        }
+        data[RAM_OFFSET..RAM_OFFSET + 0x10000].copy_from_slice(&self.ram);
+        data[DSP_OFFSET..DSP_OFFSET + 128].copy_from_slice(&self.dsp_registers);
+        data[EXTRA_RAM_OFFSET..EXTRA_RAM_OFFSET + 64].copy_from_slice(&self.extra_ram);
+        data
+    }
+}
+
+impl Id666Tags {
+    /// Tags for a song dumped from the game with `header`.
+    pub fn from_header(header: &SnesHeader) -> Self {
+        Self {
+            game_title: header.name.trim_matches(['\0', ' ']).to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Parses the tags at offset $2E, which are either stored in text or binary format.
+    fn parse(data: &[u8]) -> Self {
+        let text = |offset: usize, len: usize| {
+            let bytes = &data[offset..offset + len];
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(len);
+            String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+        };
+        let number = |offset: usize, len: usize| text(offset, len).parse().unwrap_or(0);
+        let binary_number = |offset: usize, len: usize| {
+            data[offset..offset + len]
+                .iter()
+                .rev()
+                .fold(0, |acc, b| (acc << 8) | *b as u32)
+        };
+
+        let (play_seconds, fade_millis, artist) = if Self::is_text_format(data) {
+            (number(0xA9, 3), number(0xAC, 5), text(0xB1, 32))
+        } else {
+            (
+                binary_number(0xA9, 3),
+                binary_number(0xAC, 4),
+                text(0xB0, 32),
+            )
+        };
+        Self {
+            song_title: text(0x2E, 32),
+            game_title: text(0x4E, 32),
+            dumper: text(0x6E, 16),
+            comments: text(0x7E, 32),
+            artist,
+            play_seconds,
+            fade_millis,
+        }
+    }
+
+    /// Writes the tags in text format, which is understood by all players.
+    fn write(&self, data: &mut [u8]) {
+        let mut text = |offset: usize, len: usize, value: &str| {
+            let bytes = value.as_bytes();
+            let len = bytes.len().min(len);
+            data[offset..offset + len].copy_from_slice(&bytes[..len]);
+        };
+        text(0x2E, 32, &self.song_title);
+        text(0x4E, 32, &self.game_title);
+        text(0x6E, 16, &self.dumper);
+        text(0x7E, 32, &self.comments);
+        if self.play_seconds > 0 {
+            text(0xA9, 3, &self.play_seconds.min(999).to_string());
+        }
+        if self.fade_millis > 0 {
+            text(0xAC, 5, &self.fade_millis.min(99999).to_string());
+        }
+        text(0xB1, 32, &self.artist);
+    }
+
+    /// The format is not flagged in the file. Text tags store the song length as digits, and
+    /// the artist starts one byte later than in binary tags.
+    fn is_text_format(data: &[u8]) -> bool {
+        data[0xA9..0xB1]
+            .iter()
+            .all(|b| b.is_ascii_digit() || *b == 0)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds a minimal .spc file with `program` at $0200.
+    pub fn test_spc_file(program: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; FILE_SIZE];
+        data[..SIGNATURE.len()].copy_from_slice(SIGNATURE);
+        data[0x21..0x25].copy_from_slice(&[0x1A, 0x1A, 0x1A, 30]);
+        data[0x25..0x2C].copy_from_slice(&[0x00, 0x02, 0x01, 0x02, 0x03, 0x02, 0xEF]);
+        data[RAM_OFFSET + 0x200..RAM_OFFSET + 0x200 + program.len()].copy_from_slice(program);
+        data
+    }
+
+    fn write_tag(data: &mut [u8], offset: usize, value: &[u8]) {
+        data[offset..offset + value.len()].copy_from_slice(value);
+    }
+
+    #[test]
+    fn test_parse_text_tags() {
+        let mut data = test_spc_file(&[0xE8, 0x42]);
+        write_tag(&mut data, 0x2E, b"Prelude");
+        write_tag(&mut data, 0x4E, b"Final Fantasy VII");
+        write_tag(&mut data, 0x6E, b"Dumper");
+        write_tag(&mut data, 0x9E, b"01/02/2003");
+        write_tag(&mut data, 0xA9, b"180");
+        write_tag(&mut data, 0xAC, b"10000");
+        write_tag(&mut data, 0xB1, b"Nobuo Uematsu");
+
+        let spc = SpcFile::parse(&data).unwrap();
+        assert_eq!(
+            spc.registers,
+            Spc700Registers {
+                pc: 0x0200,
+                a: 0x01,
+                x: 0x02,
+                y: 0x03,
+                psw: 0x02,
+                sp: 0xEF,
+            }
+        );
+        assert_eq!(&spc.ram[0x200..0x202], &[0xE8, 0x42]);
+        assert_eq!(
+            spc.tags.unwrap(),
+            Id666Tags {
+                song_title: "Prelude".to_string(),
+                game_title: "Final Fantasy VII".to_string(),
+                dumper: "Dumper".to_string(),
+                comments: String::new(),
+                artist: "Nobuo Uematsu".to_string(),
+                play_seconds: 180,
+                fade_millis: 10000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_binary_tags() {
+        let mut data = test_spc_file(&[]);
+        write_tag(&mut data, 0x9E, &[2, 1, 0xD3, 0x07]);
+        write_tag(&mut data, 0xA9, &[0xB4, 0x00, 0x00]);
+        write_tag(&mut data, 0xAC, &[0x10, 0x27, 0x00, 0x00]);
+        write_tag(&mut data, 0xB0, b"Nobuo Uematsu");
+
+        let tags = SpcFile::parse(&data).unwrap().tags.unwrap();
+        assert_eq!(tags.play_seconds, 180);
+        assert_eq!(tags.fade_millis, 10000);
+        assert_eq!(tags.artist, "Nobuo Uematsu");
+    }
+
+    #[test]
+    fn test_save_and_parse() {
+        let mut spc = SpcFile::parse(&test_spc_file(&[0xE8, 0x42])).unwrap();
+        spc.tags = Some(Id666Tags {
+            song_title: "Prelude".to_string(),
+            artist: "Nobuo Uematsu".to_string(),
+            play_seconds: 180,
+            fade_millis: 10000,
+            ..Id666Tags::from_header(&SnesHeader {
+                name: "FINAL FANTASY 3      ".to_string(),
+                ..Default::default()
+            })
+        });
+        spc.dsp_registers[0x5D] = 0x02;
+        spc.extra_ram[0x3F] = 0xFF;
+
+        let parsed = SpcFile::parse(&spc.to_bytes()).unwrap();
+        assert_eq!(parsed.registers, spc.registers);
+        assert_eq!(parsed.ram, spc.ram);
+        assert_eq!(parsed.dsp_registers, spc.dsp_registers);
+        assert_eq!(parsed.extra_ram, spc.extra_ram);
+        let tags = parsed.tags.unwrap();
+        assert_eq!(tags.game_title, "FINAL FANTASY 3");
+        assert_eq!(tags, spc.tags.unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid_file() {
+        assert!(SpcFile::parse(b"not an spc file").is_err());
+        assert!(SpcFile::parse(&test_spc_file(&[])[..0x1000]).is_err());
+    }
+}
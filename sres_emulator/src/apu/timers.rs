@@ -53,6 +53,10 @@ impl ApuTimer {
         self.interval_target = target;
     }
 
+    pub fn target(&self) -> u8 {
+        self.interval_target
+    }
+
     pub fn read_output(&mut self) -> u8 {
         let value = self.output_counter & 0x0F; // Only lower 4 bits
         self.output_counter = 0; // Reset on read
@@ -63,6 +67,10 @@ impl ApuTimer {
         self.output_counter & 0x0F // Only lower 4 bits
     }
 
+    pub fn set_output(&mut self, value: u8) {
+        self.output_counter = value & 0x0F;
+    }
+
     /// Update timer with SPC700 cycles (not master cycles)
     pub fn update(&mut self, spc_cycles: u32) {
         // Stage 1: Base frequency divider runs continuously
@@ -141,6 +149,14 @@ impl ApuTimers {
         }
     }
 
+    pub fn peek_target(&self, timer_id: usize) -> u8 {
+        if timer_id < 3 {
+            self.timers[timer_id].target()
+        } else {
+            0
+        }
+    }
+
     pub fn read_output(&mut self, timer_id: usize) -> u8 {
         if timer_id < 3 {
             self.timers[timer_id].read_output()
@@ -157,6 +173,12 @@ impl ApuTimers {
         }
     }
 
+    pub fn set_output(&mut self, timer_id: usize, value: u8) {
+        if timer_id < 3 {
+            self.timers[timer_id].set_output(value);
+        }
+    }
+
     /// Update all timers with SPC700 cycles
     pub fn update(&mut self, spc_cycles: u32) {
         for timer in &mut self.timers {
@@ -7,6 +7,7 @@ pub struct ClockInfo {
     pub v: u64,
     pub h_counter: u64,
     pub f: u64,
+    pub overscan: bool,
 }
 
 impl ClockInfo {
@@ -38,6 +39,7 @@ impl ClockInfo {
             v,
             h_counter,
             f,
+            overscan: false,
         }
     }
 
@@ -55,8 +57,17 @@ impl ClockInfo {
         counter / 4
     }
 
+    /// First scanline of vblank. Overscan mode (SETINI) extends the display to 239 lines.
+    pub fn vblank_start(&self) -> u64 {
+        if self.overscan {
+            240
+        } else {
+            225
+        }
+    }
+
     pub fn vblank(&self) -> bool {
-        self.v >= 225
+        self.v >= self.vblank_start()
     }
 
     // Mesen increments the frame number at the start of vblank (v=225), which complicates the
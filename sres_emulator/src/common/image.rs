@@ -104,3 +104,13 @@ pub trait Image {
     fn new(width: u32, height: u32) -> Self;
     fn set_pixel(&mut self, index: (u32, u32), value: Rgba32);
 }
+
+impl Image for image::RgbaImage {
+    fn new(width: u32, height: u32) -> Self {
+        image::RgbaImage::new(width, height)
+    }
+
+    fn set_pixel(&mut self, index: (u32, u32), value: Rgba32) {
+        self[index] = image::Rgba(value.0);
+    }
+}
@@ -2,6 +2,7 @@ use std::path::Path;
 
 use hound::WavReader;
 use hound::WavWriter;
+use image::RgbaImage;
 
 pub fn compare_wav_against_golden(data: &[i16], path_prefix: &Path) {
     let golden_path = path_prefix.with_extension("wav");
@@ -17,6 +18,20 @@ pub fn compare_wav_against_golden(data: &[i16], path_prefix: &Path) {
     }
 }
 
+pub fn compare_image_against_golden(image: &RgbaImage, path_prefix: &Path) {
+    let golden_path = path_prefix.with_extension("png");
+    if golden_path.exists() {
+        let golden = image::open(&golden_path).unwrap().into_rgba8();
+        if golden != *image {
+            let actual_path = path_prefix.with_extension("actual.png");
+            image.save(&actual_path).unwrap();
+            panic!("Image does not match golden. See {actual_path:?}");
+        }
+    } else {
+        image.save(golden_path).unwrap();
+    }
+}
+
 pub fn write_snes_wav(data: &[i16], filename: &Path) {
     let spec = hound::WavSpec {
         channels: 1,
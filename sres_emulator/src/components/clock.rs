@@ -15,6 +15,7 @@ pub struct Clock {
     v: u64,
     h_counter: u64,
     f: u64,
+    overscan: bool,
     vblank_detector: EdgeDetector,
     hv_timer_detector: EdgeDetector,
     timer_mode: HVTimerMode,
@@ -36,6 +37,7 @@ impl Clock {
             v: self.v,
             h_counter: self.h_counter,
             f: self.f,
+            overscan: self.overscan,
         }
     }
 
@@ -71,6 +73,7 @@ impl Clock {
 
     pub fn bus_write(&mut self, addr: AddressU24, value: u8) {
         match addr.offset {
+            0x2133 => self.write_setini(value),
             0x4200 => self.write_nmitimen(value),
             0x4207 => self.write_htimel(value),
             0x4208 => self.write_htimeh(value),
@@ -113,6 +116,24 @@ impl Clock {
         value
     }
 
+    /// Register $2133: SETINI - Screen mode/video select
+    /// 7  bit  0
+    /// ---- ----
+    /// EX.. HOiI
+    /// ||   ||||
+    /// ||   |||+- Screen interlace
+    /// ||   ||+-- OBJ interlace
+    /// ||   |+--- Overscan mode (0 = 224 lines, 1 = 239 lines)
+    /// ||   +---- High-res mode (pseudo 512 pixels)
+    /// |+-------- EXTBG mode
+    /// +--------- External sync
+    ///
+    /// The register belongs to the PPU, but overscan moves the start of vblank, so the clock needs
+    /// to know about it as well.
+    fn write_setini(&mut self, value: u8) {
+        self.overscan = value.bit(2);
+    }
+
     /// Register $4200: NMITIMEN - NMI, Timer and IRQ Enable/Flag
     /// 7  bit  0
     /// ---- ----
@@ -148,7 +169,7 @@ impl Clock {
         let value = self.peek_rdnmi().unwrap();
         if self.nmi_flag {
             // Fake NMI hold, do not reset nmi flag for the first 2 cyles.
-            if !(self.v == 225 && self.h_counter <= 2) {
+            if !(self.v == self.clock_info().vblank_start() && self.h_counter <= 2) {
                 self.nmi_flag = false;
             }
         }
@@ -195,7 +216,7 @@ impl Clock {
     /// +--------- Vblank flag
     fn peek_hvbjoy(&self) -> Option<u8> {
         let mut value: u8 = 0;
-        if self.v > 225 {
+        if self.v > self.clock_info().vblank_start() {
             value.set_bit(7, true);
         }
         if self.hdot() > 274 {
@@ -280,7 +301,8 @@ impl Clock {
             self.f += 1;
         }
 
-        self.vblank_detector.update_signal(self.v >= 225);
+        self.vblank_detector
+            .update_signal(self.v >= self.clock_info().vblank_start());
         self.update_timer_detector();
     }
 
@@ -326,6 +348,7 @@ impl Default for Clock {
             v: 0,
             h_counter: 0,
             f: 0,
+            overscan: false,
             dram_refresh_position: 538,
             vblank_detector: EdgeDetector::new(),
             hv_timer_detector: EdgeDetector::new(),
@@ -478,6 +501,23 @@ mod tests {
         assert!(!timer.consume_timer_interrupt());
     }
 
+    #[test]
+    fn test_overscan_vblank() {
+        let mut timer = Clock::default();
+        // Enable overscan via SETINI
+        timer.bus_write(0x2133.into(), 0x04);
+
+        // V=225: Overscan delays vblank to V=240
+        timer.advance_master_clock(1324 * 225 + 8);
+        assert_eq!(timer.v, 225);
+        assert!(!timer.nmi_flag);
+
+        // V=240: VBlank starts
+        timer.advance_master_clock(1324 * 15);
+        assert_eq!(timer.v, 240);
+        assert!(timer.nmi_flag);
+    }
+
     #[test]
     fn test_nmi_sub_cycle_accuracy() {
         static TEST_CASES: &[(u64, u64, bool, bool)] = &[
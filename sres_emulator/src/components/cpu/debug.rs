@@ -310,6 +310,7 @@ mod tests {
                 v: 123,
                 h_counter: 1226,
                 f: 1,
+                overscan: false,
             },
         }
     }
@@ -317,7 +318,7 @@ mod tests {
     #[test]
     pub fn test_from_mesen_trace() {
         assert_eq!(
-            CpuState::parse_mesen_trace(&EXAMPLE_MESEN_TRACE).unwrap(),
+            CpuState::parse_mesen_trace(EXAMPLE_MESEN_TRACE).unwrap(),
             example_trace()
         );
     }
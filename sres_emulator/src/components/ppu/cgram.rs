@@ -51,14 +51,17 @@ impl CgRam {
     ///           If cgram_byte == 1, CGDATA = (value << 8) | cgram_latch
     ///           cgram_byte = ~cgram_byte
     ///
-    pub fn write_cgdata(&mut self, value: u8) {
+    /// During active display, hardware writes the word to the palette entry currently being
+    /// rendered. The PPU passes that address in `redirect`, the CGADD address is still
+    /// incremented.
+    pub fn write_cgdata(&mut self, value: u8, redirect: Option<u8>) {
         match self.latch {
             None => {
                 self.latch = Some(value);
             }
             Some(low_byte) => {
-                self.memory[self.current_addr as usize] =
-                    Rgb15(u16::from_le_bytes([low_byte, value]));
+                let addr = redirect.unwrap_or(self.current_addr);
+                self.memory[addr as usize] = Rgb15(u16::from_le_bytes([low_byte, value]));
                 self.latch = None;
                 self.current_addr = self.current_addr.wrapping_add(1);
             }
@@ -117,9 +120,18 @@ mod tests {
     fn test_write_cgdata() {
         let mut cgram = CgRam::new();
         cgram.write_cgadd(0x42);
-        cgram.write_cgdata(0x03);
-        cgram.write_cgdata(0xE0);
+        cgram.write_cgdata(0x03, None);
+        cgram.write_cgdata(0xE0, None);
         assert_eq!(cgram.memory[0x42], Rgb15(0xE003));
+
+        // Redirected writes still increment the address
+        cgram.write_cgdata(0x01, Some(0x10));
+        cgram.write_cgdata(0x02, Some(0x10));
+        cgram.write_cgdata(0x03, None);
+        cgram.write_cgdata(0x04, None);
+        assert_eq!(cgram.memory[0x10], Rgb15(0x0201));
+        assert_eq!(cgram.memory[0x43], Rgb15(0));
+        assert_eq!(cgram.memory[0x44], Rgb15(0x0403));
     }
 
     #[test]
@@ -4,11 +4,14 @@ use super::BitDepth;
 use super::Bpp2Decoder;
 use super::Bpp4Decoder;
 use super::Bpp8Decoder;
+use super::Mode7;
 use super::Ppu;
 use super::Tile;
 use super::TileDecoder;
+use super::MODE7_PLANE_SIZE;
 use crate::common::address::AddressU15;
 use crate::common::image::Image;
+use crate::components::ppu::mode7::plane_pixel;
 
 pub struct PpuDebug<'a>(pub &'a Ppu);
 
@@ -181,6 +184,65 @@ impl PpuDebug<'_> {
         }
     }
 
+    /// Renders the full 1024x1024 Mode 7 plane.
+    pub fn render_mode7_plane<ImageT: Image>(&self) -> ImageT {
+        let mut image = ImageT::new(MODE7_PLANE_SIZE, MODE7_PLANE_SIZE);
+        let background = &self.0.state.backgrounds[0];
+        for y in 0..MODE7_PLANE_SIZE {
+            for x in 0..MODE7_PLANE_SIZE {
+                let pixel = plane_pixel(x, y, &self.0.state.vram);
+                image.set_pixel((x, y), self.0.bg_color(background, pixel, 0).into());
+            }
+        }
+        image
+    }
+
+    /// The current Mode 7 registers.
+    pub fn mode7(&self) -> Mode7 {
+        self.0.state.mode7
+    }
+
+    /// Number of scanlines with recorded Mode 7 registers.
+    pub fn mode7_scanline_count(&self) -> u32 {
+        self.0.visible_height()
+    }
+
+    /// The Mode 7 registers that were used to draw `scanline`. These differ between scanlines if
+    /// they are updated via HDMA.
+    pub fn mode7_scanline(&self, scanline: u32) -> Mode7 {
+        self.0.mode7_scanlines[scanline as usize]
+    }
+
+    pub fn mode7_info(&self, scanline: u32) -> String {
+        let mode7 = self.mode7_scanline(scanline);
+        let fixed = |value: i16| value as f32 / 256.0;
+        format!(
+            "Matrix [{:.3} {:.3}; {:.3} {:.3}]\nCenter ({}, {}) Scroll ({}, {})\nRepeat: {}{}{}",
+            fixed(mode7.a),
+            fixed(mode7.b),
+            fixed(mode7.c),
+            fixed(mode7.d),
+            mode7.center_x,
+            mode7.center_y,
+            mode7.h_offset,
+            mode7.v_offset,
+            mode7.repeat,
+            if mode7.flip_x { " FlipX" } else { "" },
+            if mode7.flip_y { " FlipY" } else { "" },
+        )
+    }
+
+    /// The area of the plane visible on screen. Contains the plane coordinates of the first and
+    /// last pixel of each scanline.
+    pub fn mode7_viewport(&self) -> Vec<((i32, i32), (i32, i32))> {
+        (0..self.mode7_scanline_count())
+            .map(|scanline| {
+                let mode7 = self.mode7_scanline(scanline);
+                (mode7.transform(0, scanline), mode7.transform(255, scanline))
+            })
+            .collect()
+    }
+
     pub fn render_palette<ImageT: Image>(&self) -> ImageT {
         let mut image = ImageT::new(128, 128);
         for y in 0..16_u32 {
@@ -197,6 +259,35 @@ impl PpuDebug<'_> {
     }
 }
 
+/// Overrides applied on top of the game's PPU settings to help debug rendering issues.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct PpuDebugOverrides {
+    /// Hides BG1-4 on both main and sub screen.
+    pub hide_bg: [bool; 4],
+    /// Hides sprites on both main and sub screen.
+    pub hide_obj: bool,
+    /// Renders the sub screen as transparent, so color math uses the fixed color.
+    pub hide_sub_screen: bool,
+    pub disable_color_math: bool,
+    /// Dims all pixels that do not come from this layer.
+    pub highlight: Option<DebugLayer>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DebugLayer {
+    Background(BackgroundId),
+    Object,
+}
+
+impl std::fmt::Display for DebugLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DebugLayer::Background(id) => write!(f, "{id}"),
+            DebugLayer::Object => write!(f, "OBJ"),
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum VramRenderSelection {
     Background(BackgroundId),
@@ -1,19 +1,28 @@
 //! Implementation of the Picture Processing Unit
 mod cgram;
 mod debug;
+mod mode7;
 mod oam;
 mod vram;
 
 use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
 
 use bitcode::Decode;
 use bitcode::Encode;
 use intbits::Bits;
 
 use self::cgram::CgRam;
+pub use self::debug::DebugLayer;
 pub use self::debug::PpuDebug;
+pub use self::debug::PpuDebugOverrides;
 pub use self::debug::VramRenderSelection;
+pub use self::mode7::Mode7;
+pub use self::mode7::Mode7Repeat;
+pub use self::mode7::MODE7_PLANE_SIZE;
 use self::oam::Oam;
+use self::oam::ScanlineSprite;
 pub use self::oam::Sprite;
 pub use self::oam::SpriteSize;
 use self::vram::Vram;
@@ -21,6 +30,7 @@ use crate::common::address::AddressU15;
 use crate::common::address::AddressU24;
 use crate::common::bus::BusDeviceU24;
 use crate::common::clock::ClockInfo;
+use crate::common::debug_events::DebugEventCollectorRef;
 use crate::common::image::Image;
 use crate::common::image::Rgb15;
 use crate::common::uint::U16Ext;
@@ -37,22 +47,51 @@ pub enum BackgroundId {
 }
 
 pub struct Ppu {
-    disabled: bool,
     headless: bool,
+    debug_event_collector: DebugEventCollectorRef<()>,
+    render_mode: RenderMode,
+    /// In `RenderMode::Segmented`, the first pixel of the current scanline not drawn yet.
+    segment_start: u32,
+    /// Mode 7 registers used to draw each scanline of the last frame, for debugging.
+    mode7_scanlines: Vec<Mode7>,
+    debug_overrides: PpuDebugOverrides,
+    /// CGRAM address of the main screen pixel at each x of the current scanline. CGRAM writes
+    /// during active display go to the address currently read by the PPU.
+    cgram_fetches: [u8; 256],
     state: PpuState,
 }
 
+/// Controls when register writes take effect on the rendered image.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Each scanline is drawn at once when it starts. Writes during the scanline only affect the
+    /// following scanlines.
+    #[default]
+    Scanline,
+    /// Scanlines are drawn in segments, split at the horizontal position of each register write.
+    /// Slower, but supports games changing registers mid-scanline.
+    Segmented,
+}
+
 #[derive(Encode, Decode)]
 pub struct PpuState {
     vram: Vram,
     bgmode: BgMode,
+    forced_blank: bool,
+    brightness: u8,
     current_clock: ClockInfo,
     bg3_priority: bool,
     backgrounds: [Background; 4],
+    overscan: bool,
+    interlace: bool,
+    obj_interlace: bool,
+    pseudo_hires: bool,
 
     framebuffer: Framebuffer,
     cgram: CgRam,
     oam: Oam,
+    obj_range_over: bool,
+    obj_time_over: bool,
     last_drawn_scanline: u64,
 
     bgofs_latch: u8,
@@ -61,8 +100,13 @@ pub struct PpuState {
     color_math_backdrop_enabled: bool,
     color_math_operation: ColorMathOperation,
     color_math_half: bool,
+    color_math_subscreen: bool,
+    direct_color: bool,
+    clip_to_black: ColorWindowRegion,
+    prevent_color_math: ColorWindowRegion,
     fixed_color: Rgb15,
 
+    mode7: Mode7,
     mode7_latch: u8,
     m7a_mul: i16,
     m7b_mul: i8,
@@ -79,18 +123,31 @@ impl Default for PpuState {
         Self {
             vram: Vram::new(),
             bgmode: BgMode::Mode0,
+            forced_blank: true,
+            brightness: 0,
             bg3_priority: false,
             current_clock: ClockInfo::default(),
             backgrounds: [Background::default(); 4],
+            overscan: false,
+            interlace: false,
+            obj_interlace: false,
+            pseudo_hires: false,
             framebuffer: Framebuffer::default(),
             cgram: CgRam::new(),
             oam: Oam::new(),
+            obj_range_over: false,
+            obj_time_over: false,
             last_drawn_scanline: 0,
             bgofs_latch: 0,
             bghofs_latch: 0,
             color_math_backdrop_enabled: false,
             color_math_operation: ColorMathOperation::Add,
             color_math_half: false,
+            color_math_subscreen: false,
+            direct_color: false,
+            clip_to_black: ColorWindowRegion::Nowhere,
+            prevent_color_math: ColorWindowRegion::Nowhere,
+            mode7: Mode7::default(),
             mode7_latch: 0,
             m7a_mul: 0,
             m7b_mul: 0,
@@ -142,12 +199,26 @@ impl BusDeviceU24 for Ppu {
     }
 
     fn write(&mut self, addr: AddressU24, value: u8) {
+        if self.render_mode == RenderMode::Segmented && !self.headless && affects_rendering(addr) {
+            self.draw_current_segment();
+        }
         match addr.offset {
             0x2100 => self.write_inidisp(value),
             0x2101 => self.state.oam.write_objsel(value),
             0x2102 => self.state.oam.write_oamaddl(value),
             0x2103 => self.state.oam.write_oamaddh(value),
-            0x2104 => self.state.oam.write_oamdata(value),
+            0x2104 => {
+                let redirect = self.oam_write_redirect();
+                if let Some(target) = redirect {
+                    self.report_restricted_write(
+                        "OAM",
+                        addr,
+                        value,
+                        format!("goes to ${target:03X}"),
+                    );
+                }
+                self.state.oam.write_oamdata(value, redirect)
+            }
             0x2105 => self.write_bgmode(value),
             0x2107..=0x210A => self.write_bgnsc(addr, value),
             0x210B => self.write_bg12nba(value),
@@ -157,16 +228,46 @@ impl BusDeviceU24 for Ppu {
             0x2115 => self.state.vram.write_vmain(value),
             0x2116 => self.state.vram.write_vmaddl(value),
             0x2117 => self.state.vram.write_vmaddh(value),
-            0x2118 => self.state.vram.write_vmdatal(value),
-            0x2119 => self.state.vram.write_vmdatah(value),
+            0x2118 => {
+                let access_allowed = self.vram_access_allowed();
+                if !access_allowed {
+                    self.report_restricted_write("VRAM", addr, value, "ignored".to_string());
+                }
+                self.state.vram.write_vmdatal(value, access_allowed)
+            }
+            0x2119 => {
+                let access_allowed = self.vram_access_allowed();
+                if !access_allowed {
+                    self.report_restricted_write("VRAM", addr, value, "ignored".to_string());
+                }
+                self.state.vram.write_vmdatah(value, access_allowed)
+            }
             0x2121 => self.state.cgram.write_cgadd(value),
-            0x2122 => self.state.cgram.write_cgdata(value),
+            0x2122 => {
+                let redirect = self.cgram_write_redirect();
+                if let Some(target) = redirect {
+                    self.report_restricted_write(
+                        "CGRAM",
+                        addr,
+                        value,
+                        format!("goes to ${target:02X}"),
+                    );
+                }
+                self.state.cgram.write_cgdata(value, redirect)
+            }
             0x212C => self.write_tm(value),
             0x212D => self.write_ts(value),
+            0x2130 => self.write_cgwsel(value),
             0x2131 => self.write_cdadsub(value),
             0x2132 => self.write_coldata(value),
+            0x2133 => self.write_setini(value),
+            0x211A => self.state.mode7.write_m7sel(value),
             0x211B => self.write_m7a(value),
             0x211C => self.write_m7b(value),
+            0x211D => self.write_m7c(value),
+            0x211E => self.write_m7d(value),
+            0x211F => self.write_m7x(value),
+            0x2120 => self.write_m7y(value),
             _ => log::warn!(
                 "PPU: Unhandled write to {:04X} = {:02X}",
                 addr.offset,
@@ -176,20 +277,35 @@ impl BusDeviceU24 for Ppu {
     }
 
     fn update_clock(&mut self, new_clock: ClockInfo) {
-        if self.disabled {
-            return;
-        }
+        // Update the clock first so the scanline is drawn into the correct interlace field.
+        self.state.current_clock = new_clock;
         if new_clock.v != self.state.last_drawn_scanline {
+            if !self.state.forced_blank {
+                if new_clock.v == 0 {
+                    self.state.obj_range_over = false;
+                    self.state.obj_time_over = false;
+                } else if new_clock.v == new_clock.vblank_start() {
+                    self.state.oam.reload_address();
+                }
+            }
             if !self.headless {
-                self.draw_scanline(new_clock.v as u32);
+                match self.render_mode {
+                    RenderMode::Scanline => self.draw_scanline(new_clock.v as u32),
+                    RenderMode::Segmented => {
+                        // Finish the previous scanline with the registers as of its end.
+                        let previous_line = self.state.last_drawn_scanline as u32;
+                        self.draw_scanline_segment(previous_line, self.segment_start..256);
+                        self.segment_start = 0;
+                    }
+                }
             }
             self.state.last_drawn_scanline = new_clock.v;
         }
-        self.state.current_clock = new_clock;
     }
 
     fn reset(&mut self) {
         self.state = PpuState::default();
+        self.segment_start = 0;
     }
 }
 
@@ -200,15 +316,32 @@ enum Layer {
 }
 
 impl Ppu {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    pub fn new(debug_event_collector: DebugEventCollectorRef<()>) -> Self {
         Self {
-            disabled: false,
             headless: false,
+            debug_event_collector,
+            render_mode: RenderMode::Scanline,
+            segment_start: 0,
+            mode7_scanlines: vec![Mode7::default(); 240],
+            debug_overrides: PpuDebugOverrides::default(),
+            cgram_fetches: [0; 256],
             state: PpuState::default(),
         }
     }
 
+    pub fn debug_overrides(&self) -> PpuDebugOverrides {
+        self.debug_overrides
+    }
+
+    pub fn set_debug_overrides(&mut self, debug_overrides: PpuDebugOverrides) {
+        self.debug_overrides = debug_overrides;
+    }
+
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+        self.segment_start = 0;
+    }
+
     /// Only used for benchmarks, runs full PPU emulation but does not render
     pub fn force_headless(&mut self) {
         self.headless = true;
@@ -219,8 +352,15 @@ impl Ppu {
     }
 
     /// Swap the current framebuffer with a provided buffer to avoid copying
+    ///
+    /// In interlace mode each frame only renders every other line, so the framebuffer is copied
+    /// instead to keep the lines of the previous field.
     pub fn swap_framebuffer(&mut self, buffer: &mut Framebuffer) {
-        std::mem::swap(&mut self.state.framebuffer, buffer);
+        if self.state.interlace {
+            buffer.clone_from(&self.state.framebuffer);
+        } else {
+            std::mem::swap(&mut self.state.framebuffer, buffer);
+        }
     }
 
     pub fn load_state(&mut self, encoded: &[u8]) -> anyhow::Result<()> {
@@ -236,42 +376,144 @@ impl Ppu {
         PpuDebug(self)
     }
 
+    /// Number of visible scanlines, which is extended from 224 to 239 in overscan mode.
+    fn visible_height(&self) -> u32 {
+        if self.state.overscan {
+            239
+        } else {
+            224
+        }
+    }
+
+    /// True if backgrounds are rendered at 512 pixels per scanline (BG modes 5 and 6).
+    fn bg_hires(&self) -> bool {
+        matches!(self.state.bgmode, BgMode::Mode5 | BgMode::Mode6)
+    }
+
+    /// True if the scanline is output at 512 pixels, interleaving sub and main screen.
+    fn hires(&self) -> bool {
+        self.bg_hires() || self.state.pseudo_hires
+    }
+
     pub fn draw_scanline(&mut self, screen_y: u32) {
-        if screen_y >= 224 {
+        self.draw_scanline_segment(screen_y, 0..256);
+    }
+
+    /// Draws the current scanline up to the current dot, before a register write takes effect.
+    fn draw_current_segment(&mut self) {
+        // Pixels are output from dot 22 to dot 277.
+        let x = (self.state.current_clock.hdot() as u32).clamp(22, 278) - 22;
+        if x > self.segment_start {
+            let screen_y = self.state.last_drawn_scanline as u32;
+            self.draw_scanline_segment(screen_y, self.segment_start..x);
+            self.segment_start = x;
+        }
+    }
+
+    /// Renders the full scanline, but only outputs the pixels in `segment` to the framebuffer.
+    fn draw_scanline_segment(&mut self, screen_y: u32, segment: Range<u32>) {
+        let visible_height = self.visible_height();
+        if screen_y >= visible_height {
+            return;
+        }
+        self.mode7_scanlines[screen_y as usize] = self.state.mode7;
+        let hires = self.hires();
+        let height = if self.state.interlace {
+            visible_height * 2
+        } else {
+            visible_height
+        };
+        if screen_y == 0 || self.state.framebuffer.height() != height {
+            let width = if hires { 512 } else { 256 };
+            self.state.framebuffer.resize(width, height);
+        } else if hires && self.state.framebuffer.width() == 256 {
+            // Switched to hires mid-frame. Scale up the lines rendered so far.
+            self.state.framebuffer.double_width();
+        }
+
+        // In interlace mode, odd frames render the odd lines of the 448 line output.
+        let field = (self.state.current_clock.f % 2) as u32;
+        let output_y = if self.state.interlace {
+            screen_y * 2 + field
+        } else {
+            screen_y
+        };
+        if self.state.forced_blank {
+            let scale = self.state.framebuffer.width() / 256;
+            for x in (segment.start * scale)..(segment.end * scale) {
+                self.state.framebuffer[(x, output_y)] = Rgb15(0);
+            }
             return;
         }
 
-        let mut bg_data: [[(u8, bool); 256]; 4] = [
-            [(0, false); 256],
-            [(0, false); 256],
-            [(0, false); 256],
-            [(0, false); 256],
+        // Only the hires modes 5 and 6 render backgrounds at the full interlaced resolution.
+        let bg_y = if self.state.interlace && self.bg_hires() {
+            output_y
+        } else {
+            screen_y
+        };
+
+        let mut bg_data: [[(u8, bool, u8); 512]; 4] = [
+            [(0, false, 0); 512],
+            [(0, false, 0); 512],
+            [(0, false, 0); 512],
+            [(0, false, 0); 512],
         ];
-        let layers = self.decode_bgmode(screen_y, &mut bg_data);
+        let layers = self.decode_bgmode(bg_y, &mut bg_data);
+
+        // In BG modes 5 and 6, even pixels are shown on the sub screen and odd pixels on the main
+        // screen.
+        let mut main_bg = [[(0, false, 0); 256]; 4];
+        let mut sub_bg = [[(0, false, 0); 256]; 4];
+        let bg_hires = self.bg_hires();
+        for id in 0..4 {
+            for x in 0..256 {
+                if bg_hires {
+                    sub_bg[id][x] = bg_data[id][x * 2];
+                    main_bg[id][x] = bg_data[id][x * 2 + 1];
+                } else {
+                    sub_bg[id][x] = bg_data[id][x];
+                    main_bg[id][x] = bg_data[id][x];
+                }
+            }
+        }
 
         let mut obj_data: [(u8, u8); 256] = [(0, 0); 256];
-        self.decode_obj(screen_y, &mut obj_data);
+        self.decode_obj(screen_y, field, &mut obj_data);
 
         // Render sub screen first, it'll be used for blending while rendering the main screen.
-        let mut raw_sub = [self.state.fixed_color; 256];
-        for layer in layers.iter().rev() {
+        // Transparent pixels are None and show the fixed color.
+        // Also keeps track of the layer each pixel comes from for `PpuDebugOverrides::highlight`.
+        let mut sub: [Option<Rgb15>; 256] = [None; 256];
+        let mut sub_layers: [Option<DebugLayer>; 256] = [None; 256];
+        let overrides = self.debug_overrides;
+        let sub_screen_layers = if overrides.hide_sub_screen {
+            &[]
+        } else {
+            layers
+        };
+        for layer in sub_screen_layers.iter().rev() {
             match layer {
                 Layer::Background(id, layer_priority) => {
                     let bg = self.state.backgrounds[*id as usize];
-                    if bg.bit_depth == BitDepth::Disabled || !bg.subscreen_enabled {
+                    if bg.bit_depth == BitDepth::Disabled
+                        || !bg.subscreen_enabled
+                        || overrides.hide_bg[*id as usize]
+                    {
                         continue;
                     }
-                    for (x, (pixel, priority)) in bg_data[*id as usize].iter().enumerate() {
+                    for (x, (pixel, priority, palette)) in sub_bg[*id as usize].iter().enumerate() {
                         if layer_priority != priority {
                             continue;
                         }
                         if *pixel > 0 {
-                            raw_sub[x] = self.state.cgram[bg.palette_addr + pixel];
+                            sub[x] = Some(self.bg_color(&bg, *pixel, *palette));
+                            sub_layers[x] = Some(DebugLayer::Background(*id));
                         }
                     }
                 }
                 Layer::Object(layer_priority) => {
-                    if !self.state.oam.sub_enabled {
+                    if !self.state.oam.sub_enabled || overrides.hide_obj {
                         continue;
                     }
                     for (x, (pixel, priority)) in obj_data.iter().enumerate() {
@@ -279,65 +521,42 @@ impl Ppu {
                             continue;
                         }
                         if *pixel > 0 {
-                            raw_sub[x] = self.state.cgram[*pixel];
+                            sub[x] = Some(self.state.cgram[*pixel]);
+                            sub_layers[x] = Some(DebugLayer::Object);
                         }
                     }
                 }
             }
         }
 
-        // Pre-invert subscreen so we don't have to branch for each pixel
-        let sub = match self.state.color_math_operation {
-            ColorMathOperation::Add => {
-                raw_sub.map(|pixel| (pixel.r() as i16, pixel.g() as i16, pixel.b() as i16))
-            }
-            ColorMathOperation::Subtract => raw_sub.map(|pixel| {
-                (
-                    -(pixel.r() as i16),
-                    -(pixel.g() as i16),
-                    -(pixel.b() as i16),
-                )
-            }),
-        };
-        let div_factor = if self.state.color_math_half { 2 } else { 1 };
-
-        // Render main screen
-        let mut scanline = if self.state.color_math_backdrop_enabled {
-            sub.map(|pixel| (self.state.cgram[0] + pixel) / div_factor)
-        } else {
-            [self.state.cgram[0]; 256]
-        };
-
+        // Render main screen, keeping track of which pixels have color math enabled.
+        let mut main = [(self.state.cgram[0], self.state.color_math_backdrop_enabled); 256];
+        let mut main_cgram = [0_u8; 256];
+        let mut main_layers: [Option<DebugLayer>; 256] = [None; 256];
         for layer in layers.iter().rev() {
             match layer {
                 Layer::Background(id, layer_priority) => {
                     let bg = self.state.backgrounds[*id as usize];
-                    if bg.bit_depth == BitDepth::Disabled || !bg.main_enabled {
+                    if bg.bit_depth == BitDepth::Disabled
+                        || !bg.main_enabled
+                        || overrides.hide_bg[*id as usize]
+                    {
                         continue;
                     }
-                    if bg.color_math_enabled {
-                        for (x, (pixel, priority)) in bg_data[*id as usize].iter().enumerate() {
-                            if layer_priority != priority {
-                                continue;
-                            }
-                            if *pixel > 0 {
-                                scanline[x] = (self.state.cgram[bg.palette_addr + pixel] + sub[x])
-                                    / div_factor;
-                            }
+                    for (x, (pixel, priority, palette)) in main_bg[*id as usize].iter().enumerate()
+                    {
+                        if layer_priority != priority {
+                            continue;
                         }
-                    } else {
-                        for (x, (pixel, priority)) in bg_data[*id as usize].iter().enumerate() {
-                            if layer_priority != priority {
-                                continue;
-                            }
-                            if *pixel > 0 {
-                                scanline[x] = self.state.cgram[bg.palette_addr + pixel];
-                            }
+                        if *pixel > 0 {
+                            main[x] = (self.bg_color(&bg, *pixel, *palette), bg.color_math_enabled);
+                            main_cgram[x] = bg.palette_addr.wrapping_add(*pixel);
+                            main_layers[x] = Some(DebugLayer::Background(*id));
                         }
                     }
                 }
                 Layer::Object(layer_priority) => {
-                    if !self.state.oam.main_enabled {
+                    if !self.state.oam.main_enabled || overrides.hide_obj {
                         continue;
                     }
                     for (x, (pixel, priority)) in obj_data.iter().enumerate() {
@@ -345,17 +564,119 @@ impl Ppu {
                             continue;
                         }
                         if *pixel > 0 {
-                            scanline[x] = self.state.cgram[*pixel];
+                            // Only sprite palettes 4-7 (CGRAM $C0-$FF) participate in color math.
+                            main[x] = (
+                                self.state.cgram[*pixel],
+                                self.state.oam.color_math_enabled && *pixel >= 0xC0,
+                            );
+                            main_cgram[x] = *pixel;
+                            main_layers[x] = Some(DebugLayer::Object);
                         }
                     }
                 }
             }
         }
+
+        self.cgram_fetches = main_cgram;
+
+        let mut scanline = [Rgb15(0); 256];
         for x in 0..256 {
-            self.state.framebuffer[(x, screen_y)] = scanline[x as usize];
+            let (color, math_enabled) = main[x];
+            scanline[x] = self.color_math(color, math_enabled, sub[x]);
+        }
+        let mut raw_sub = sub.map(|pixel| pixel.unwrap_or(self.state.fixed_color));
+        if self.state.brightness < 15 {
+            for pixel in scanline.iter_mut().chain(raw_sub.iter_mut()) {
+                *pixel = apply_brightness(*pixel, self.state.brightness);
+            }
+        }
+        if let Some(highlight) = overrides.highlight {
+            // Dim all pixels not coming from the highlighted layer.
+            let pixels = scanline.iter_mut().zip(main_layers.iter());
+            let sub_pixels = raw_sub.iter_mut().zip(sub_layers.iter());
+            for (pixel, layer) in pixels.chain(sub_pixels) {
+                if *layer != Some(highlight) {
+                    *pixel = apply_brightness(*pixel, 4);
+                }
+            }
+        }
+
+        if hires {
+            for x in segment.clone() {
+                self.state.framebuffer[(x * 2, output_y)] = raw_sub[x as usize];
+                self.state.framebuffer[(x * 2 + 1, output_y)] = scanline[x as usize];
+            }
+        } else if self.state.framebuffer.width() == 512 {
+            for x in segment.clone() {
+                self.state.framebuffer[(x * 2, output_y)] = scanline[x as usize];
+                self.state.framebuffer[(x * 2 + 1, output_y)] = scanline[x as usize];
+            }
+        } else {
+            for x in segment.clone() {
+                self.state.framebuffer[(x, output_y)] = scanline[x as usize];
+            }
+        }
+    }
+
+    /// Returns the color of a non-transparent background pixel.
+    ///
+    /// With direct color enabled, 8bpp backgrounds interpret the pixel value as BBGGGRRR color
+    /// instead of a CGRAM index. The bgr bits of the tilemap `palette` extend each channel by
+    /// one bit:
+    ///
+    /// Red:   RRRr0, Green: GGGg0, Blue: BBb00
+    fn bg_color(&self, bg: &Background, pixel: u8, palette: u8) -> Rgb15 {
+        if self.state.direct_color && bg.bit_depth == BitDepth::Bpp8 {
+            let mut color = Rgb15(0);
+            color.set_r(pixel.bits(0..=2) << 2 | (palette.bit(0) as u8) << 1);
+            color.set_g(pixel.bits(3..=5) << 2 | (palette.bit(1) as u8) << 1);
+            color.set_b(pixel.bits(6..=7) << 3 | (palette.bit(2) as u8) << 2);
+            color
+        } else {
+            self.state.cgram[bg.palette_addr + pixel]
         }
     }
 
+    /// Applies color math as configured by CGWSEL and CGADSUB to a main screen pixel.
+    ///
+    /// `sub` is the sub screen pixel, or None if the sub screen is transparent.
+    fn color_math(&self, main: Rgb15, math_enabled: bool, sub: Option<Rgb15>) -> Rgb15 {
+        // Windows are not implemented, so the color window is always empty.
+        let in_color_window = false;
+        if self.debug_overrides.disable_color_math {
+            return main;
+        }
+        let clipped = self.state.clip_to_black.applies(in_color_window);
+        let main = if clipped { Rgb15(0) } else { main };
+        if !math_enabled || self.state.prevent_color_math.applies(in_color_window) {
+            return main;
+        }
+
+        let (addend, half) = match (self.state.color_math_subscreen, sub) {
+            (true, Some(sub)) => (sub, self.state.color_math_half && !clipped),
+            // Transparent sub screen pixels use the fixed color and are never halved.
+            (true, None) => (self.state.fixed_color, false),
+            (false, _) => (
+                self.state.fixed_color,
+                self.state.color_math_half && !clipped,
+            ),
+        };
+        let blend = |a: u8, b: u8| -> u8 {
+            let value = match self.state.color_math_operation {
+                ColorMathOperation::Add => a as i16 + b as i16,
+                ColorMathOperation::Subtract => (a as i16 - b as i16).max(0),
+            };
+            let value = if half { value / 2 } else { value };
+            value.min(0x1F) as u8
+        };
+
+        let mut result = Rgb15(0);
+        result.set_r(blend(main.r(), addend.r()));
+        result.set_g(blend(main.g(), addend.g()));
+        result.set_b(blend(main.b(), addend.b()));
+        result
+    }
+
     /// Decodes background data and determines layer priorities
     ///
     /// Follows the following table from snes.nesdev.org:
@@ -372,7 +693,11 @@ impl Ppu {
     ///  6  | 4             |  Yes   |   S3 H1    S2       S1 L1    S0
     ///  7  | 8             |   No   |   S3       S2       S1 L1    S0
     /// 7EXT| 8   7         |   No   |   S3       S2 H2    S1 L1    S0 L2
-    fn decode_bgmode(&self, screen_y: u32, bg_data: &mut [[(u8, bool); 256]; 4]) -> &[Layer] {
+    fn decode_bgmode(
+        &self,
+        screen_y: u32,
+        bg_data: &mut [[(u8, bool, u8); 512]; 4],
+    ) -> &'static [Layer] {
         use BackgroundId::*;
         use Layer::*;
 
@@ -417,12 +742,35 @@ impl Ppu {
                 self.decode_bg::<Bpp4Decoder>(screen_y, BG2, &mut (*bg_data)[1]);
                 &[S3, H1, S2, H2, S1, L1, S0, L2]
             }
+            BgMode::Mode4 => {
+                self.decode_bg::<Bpp8Decoder>(screen_y, BG1, &mut (*bg_data)[0]);
+                self.decode_bg::<Bpp2Decoder>(screen_y, BG2, &mut (*bg_data)[1]);
+                &[S3, H1, S2, H2, S1, L1, S0, L2]
+            }
             BgMode::Mode5 => {
                 self.decode_bg::<Bpp4Decoder>(screen_y, BG1, &mut (*bg_data)[0]);
                 self.decode_bg::<Bpp2Decoder>(screen_y, BG2, &mut (*bg_data)[1]);
                 &[S3, H1, S2, H2, S1, L1, S0, L2]
             }
-            _ => panic!("Unsupported BG mode: {}", self.state.bgmode),
+            BgMode::Mode6 => {
+                self.decode_bg::<Bpp4Decoder>(screen_y, BG1, &mut (*bg_data)[0]);
+                &[S3, H1, S2, S1, L1, S0]
+            }
+            BgMode::Mode7 => {
+                self.decode_mode7(screen_y, &mut (*bg_data)[0]);
+                &[S3, S2, S1, L1, S0]
+            }
+        }
+    }
+
+    fn decode_mode7(&self, screen_y: u32, data: &mut [(u8, bool, u8); 512]) {
+        let bg = self.state.backgrounds[0];
+        if !(bg.main_enabled || bg.subscreen_enabled) {
+            return;
+        }
+        for screen_x in 0..256 {
+            let pixel = self.state.mode7.pixel(screen_x, screen_y, &self.state.vram);
+            data[screen_x as usize] = (pixel, false, 0);
         }
     }
 
@@ -430,38 +778,113 @@ impl Ppu {
         &self,
         screen_y: u32,
         background_id: BackgroundId,
-        data: &mut [(u8, bool); 256],
+        data: &mut [(u8, bool, u8); 512],
     ) {
         let bg = self.state.backgrounds[background_id as usize];
         if bg.bit_depth == BitDepth::Disabled || !(bg.main_enabled || bg.subscreen_enabled) {
             return;
         }
 
-        let y = screen_y + bg.v_offset;
-        for screen_x in 0..256 {
-            let x = screen_x + bg.h_offset;
+        if self.bg_hires() {
+            // Tiles are 16 pixels wide, made up of two consecutive tiles of the tileset.
+            for screen_x in 0..512 {
+                let column = (screen_x / 2 + (bg.h_offset & 7)) / 8;
+                let (h_offset, v_offset) = self.bg_offsets(background_id, column);
+                let x = screen_x + h_offset * 2;
+                let y = screen_y + v_offset;
+
+                let mut tile = bg.get_tile::<TileDecoderT>(x / 16, y / 8, &self.state.vram);
+                if (x % 16 >= 8) != tile.flip_h {
+                    tile = tile.next();
+                }
+                let pixel = tile.row(y % 8, &self.state.vram).pixel(x % 8);
+                data[screen_x as usize] = (pixel, tile.priority, tile.palette);
+            }
+        } else {
+            for screen_x in 0..256 {
+                let column = (screen_x + (bg.h_offset & 7)) / 8;
+                let (h_offset, v_offset) = self.bg_offsets(background_id, column);
+                let x = screen_x + h_offset;
+                let y = screen_y + v_offset;
+
+                let tile = bg.get_tile::<TileDecoderT>(x / 8, y / 8, &self.state.vram);
+                let pixel = tile.row(y % 8, &self.state.vram).pixel(x % 8);
+                data[screen_x as usize] = (pixel, tile.priority, tile.palette);
+            }
+        }
+    }
 
-            let tile = bg.get_tile::<TileDecoderT>(x / 8, y / 8, &self.state.vram);
-            let pixel = tile.row(y % 8, &self.state.vram).pixel(x % 8);
-            data[screen_x as usize] = (pixel, tile.priority);
+    /// Returns the horizontal and vertical scroll offsets of a background for the tile `column`
+    /// on screen.
+    ///
+    /// In modes 2, 4 and 6 the BG3 tilemap is used as offset-per-tile table for BG1 and BG2:
+    /// The first row of BG3 contains horizontal offsets, the second row vertical offsets. In mode
+    /// 4 there is only one row and bit 15 selects between horizontal and vertical.
+    ///
+    /// 15  bit  8   7  bit  0
+    ///  ---- ----   ---- ----
+    ///  V21. ..OO   OOOO OOOO
+    ///  |||    ||   |||| ||||
+    ///  |||    ++---++++-++++- Offset (horizontal offsets ignore the low 3 bits)
+    ///  ||+------------------- Apply to BG1
+    ///  |+-------------------- Apply to BG2
+    ///  +--------------------- Mode 4 only: 0 = horizontal offset, 1 = vertical offset
+    ///
+    /// The first column on screen is never affected.
+    fn bg_offsets(&self, background_id: BackgroundId, column: u32) -> (u32, u32) {
+        let bg = &self.state.backgrounds[background_id as usize];
+        let mut offsets = (bg.h_offset, bg.v_offset);
+        let opt_bg = &self.state.backgrounds[BackgroundId::BG3 as usize];
+        if opt_bg.bit_depth != BitDepth::Opt
+            || column == 0
+            || !matches!(background_id, BackgroundId::BG1 | BackgroundId::BG2)
+        {
+            return offsets;
         }
+
+        let opt_x = (column - 1) + opt_bg.h_offset / 8;
+        let opt_y = opt_bg.v_offset / 8;
+        let first_entry = opt_bg.tilemap_entry(opt_x, opt_y, &self.state.vram);
+        let (h_entry, v_entry) = match self.state.bgmode {
+            BgMode::Mode4 if first_entry.bit(15) => (None, Some(first_entry)),
+            BgMode::Mode4 => (Some(first_entry), None),
+            _ => (
+                Some(first_entry),
+                Some(opt_bg.tilemap_entry(opt_x, opt_y + 1, &self.state.vram)),
+            ),
+        };
+
+        let apply_bit = 13 + background_id as usize;
+        if let Some(entry) = h_entry.filter(|entry| entry.bit(apply_bit)) {
+            offsets.0 = (entry.bits(3..=9) << 3) as u32 | (bg.h_offset & 7);
+        }
+        if let Some(entry) = v_entry.filter(|entry| entry.bit(apply_bit)) {
+            offsets.1 = entry.bits(0..=9) as u32;
+        }
+        offsets
     }
 
-    fn decode_obj(&self, screen_y: u32, obj_data: &mut [(u8, u8); 256]) {
-        // `get_all_sprites_on_scanline` returns high OAM index first so lower indices overwrite
-        // (matching hardware: lower OAM index wins on overlaps).
-        let sprites = self.state.oam.get_all_sprites_on_scanline(screen_y);
-        for (sprite, row) in sprites {
+    fn decode_obj(&mut self, screen_y: u32, field: u32, obj_data: &mut [(u8, u8); 256]) {
+        let evaluation = self
+            .state
+            .oam
+            .evaluate_scanline(screen_y, self.state.obj_interlace);
+        self.state.obj_range_over |= evaluation.range_over;
+        self.state.obj_time_over |= evaluation.time_over;
+
+        // Sprites are ordered lowest priority first, so higher priority sprites overwrite them.
+        for ScanlineSprite { sprite, row, tiles } in evaluation.sprites {
+            // OBJ interlace squashes sprites to half height by rendering every other row per field.
+            let row = if self.state.obj_interlace {
+                row * 2 + field
+            } else {
+                row
+            };
             let row_coarse = row / 8;
             let row_fine = row % 8;
-            if sprite.x >= 256 {
-                continue;
-            }
+            let sprite_x = sprite.x as i32;
 
-            for coarse_x in 0..sprite.coarse_width() {
-                if sprite.x + coarse_x * 8 >= 256 {
-                    continue;
-                }
+            for coarse_x in tiles {
                 let tile_x = if sprite.hflip {
                     sprite.coarse_width() - coarse_x - 1
                 } else {
@@ -480,12 +903,12 @@ impl Ppu {
                     sprite.vflip,
                 );
                 for (fine_x, pixel) in tile.row(row_fine, &self.state.vram).pixels() {
-                    if sprite.x + coarse_x * 8 + fine_x >= 256 {
+                    let x = sprite_x + (coarse_x * 8 + fine_x) as i32;
+                    if !(0..256).contains(&x) {
                         continue;
                     }
                     if pixel > 0 {
-                        obj_data[(sprite.x + coarse_x * 8 + fine_x) as usize] =
-                            (sprite.palette_addr() + pixel, sprite.priority);
+                        obj_data[x as usize] = (sprite.palette_addr() + pixel, sprite.priority);
                     }
                 }
             }
@@ -500,8 +923,48 @@ impl Ppu {
     /// |    ++++- Screen brightness (linear steps from 0 = none to $F = full)
     /// +--------- Force blanking
     fn write_inidisp(&mut self, value: u8) {
-        log::info!("INIDISP = {value:08b}");
-        self.disabled = value.bit(7);
+        self.state.forced_blank = value.bit(7);
+        self.state.brightness = value.low_nibble();
+    }
+
+    /// VRAM and OAM can only be written to during vblank or forced blank.
+    fn vram_access_allowed(&self) -> bool {
+        self.state.forced_blank
+            || self.state.current_clock.v >= self.state.current_clock.vblank_start()
+    }
+
+    /// OAM writes during active display go to the address read by sprite evaluation instead.
+    fn oam_write_redirect(&self) -> Option<u16> {
+        if self.vram_access_allowed() {
+            return None;
+        }
+        Some(
+            self.state
+                .oam
+                .evaluation_addr(self.state.current_clock.hdot()),
+        )
+    }
+
+    /// CGRAM writes while pixels are output go to the address of the pixel currently drawn
+    /// instead. CGRAM can be written to normally during hblank.
+    fn cgram_write_redirect(&self) -> Option<u8> {
+        let hdot = self.state.current_clock.hdot();
+        if self.vram_access_allowed() || !(22..278).contains(&hdot) {
+            return None;
+        }
+        Some(self.cgram_fetches[hdot as usize - 22])
+    }
+
+    /// Emits a debugger error for writes that are ignored or redirected because they happen during
+    /// active display. These work in emulators without access restrictions, but break on real
+    /// hardware.
+    fn report_restricted_write(&self, memory: &str, addr: AddressU24, value: u8, effect: String) {
+        let clock = self.state.current_clock;
+        self.debug_event_collector.on_error(format!(
+            "{memory} write {addr} = {value:02X} during active display {effect} (V={}, H={})",
+            clock.v,
+            clock.hdot()
+        ));
     }
 
     /// Register 2105: BGMODE
@@ -620,7 +1083,12 @@ impl Ppu {
     /// On write: BGnHOFS = (value << 8) | (bgofs_latch & ~7) | (bghofs_latch & 7)
     ///           bgofs_latch = value
     ///           bghofs_latch = value
+    ///
+    /// Note: BG1HOFS uses the same address as M7HOFS
     fn write_bgnhofs(&mut self, addr: AddressU24, value: u8) {
+        if addr.offset == 0x210D {
+            self.state.mode7.h_offset = self.latch_mode7_value(value);
+        }
         let bg_id = ((addr.offset - 0x210D) / 2) as usize;
         self.state.backgrounds[bg_id].h_offset = ((value as u32) << 8)
             | ((self.state.bgofs_latch as u32) & !7)
@@ -641,6 +1109,9 @@ impl Ppu {
     ///
     /// Note: BG1VOFS uses the same address as M7VOFS
     fn write_bgnvofs(&mut self, addr: AddressU24, value: u8) {
+        if addr.offset == 0x210E {
+            self.state.mode7.v_offset = self.latch_mode7_value(value);
+        }
         let bg_id = ((addr.offset - 0x210E) / 2) as usize;
         self.state.backgrounds[bg_id].v_offset =
             ((value as u32) << 8) | (self.state.bgofs_latch as u32);
@@ -681,6 +1152,24 @@ impl Ppu {
         self.state.oam.sub_enabled = value.bit(4);
     }
 
+    /// Register 2130: CGWSEL - Color addition select
+    /// 7  bit  0
+    /// ---- ----
+    /// MMSS ..AD
+    /// |||| ||||
+    /// |||| |||+- Direct color mode (8bpp backgrounds)
+    /// |||| ||+-- Addend (0 = fixed color, 1 = sub screen)
+    /// ||++------ Sub screen transparent region (prevent color math)
+    /// ++-------- Main screen black region (clip colors to black)
+    ///
+    /// Regions: 0 = nowhere, 1 = outside color window, 2 = inside color window, 3 = everywhere
+    fn write_cgwsel(&mut self, value: u8) {
+        self.state.direct_color = value.bit(0);
+        self.state.color_math_subscreen = value.bit(1);
+        self.state.prevent_color_math = ColorWindowRegion::from(value.bits(4..=5));
+        self.state.clip_to_black = ColorWindowRegion::from(value.bits(6..=7));
+    }
+
     /// Register 2131: CGADSUB - Color math control
     /// 7  bit  0
     /// ---- ----
@@ -741,6 +1230,7 @@ impl Ppu {
     ///           mode7_latch = value
     fn write_m7a(&mut self, value: u8) {
         self.state.m7a_mul = (((value as u16) << 8) | self.state.mode7_latch as u16) as i16;
+        self.state.mode7.a = self.state.m7a_mul;
         self.state.mode7_latch = value;
     }
 
@@ -756,7 +1246,87 @@ impl Ppu {
     ///           mode7_latch = value
     fn write_m7b(&mut self, value: u8) {
         self.state.m7b_mul = value as i8;
+        self.state.mode7.b = (((value as u16) << 8) | self.state.mode7_latch as u16) as i16;
+        self.state.mode7_latch = value;
+    }
+
+    /// Register 211D: M7C - Mode 7 Matrix C
+    /// 15  bit  8   7  bit  0
+    ///  ---- ----   ---- ----
+    ///  DDDD DDDD   dddd dddd
+    ///  |||| ||||   |||| ||||
+    ///  ++++-++++---++++-++++- Mode 7 matrix C (8.8 fixed point)
+    ///
+    /// On write: M7C = (value << 8) | mode7_latch
+    ///           mode7_latch = value
+    fn write_m7c(&mut self, value: u8) {
+        self.state.mode7.c = (((value as u16) << 8) | self.state.mode7_latch as u16) as i16;
+        self.state.mode7_latch = value;
+    }
+
+    /// Register 211E: M7D - Mode 7 Matrix D
+    /// 15  bit  8   7  bit  0
+    ///  ---- ----   ---- ----
+    ///  DDDD DDDD   dddd dddd
+    ///  |||| ||||   |||| ||||
+    ///  ++++-++++---++++-++++- Mode 7 matrix D (8.8 fixed point)
+    ///
+    /// On write: M7D = (value << 8) | mode7_latch
+    ///           mode7_latch = value
+    fn write_m7d(&mut self, value: u8) {
+        self.state.mode7.d = (((value as u16) << 8) | self.state.mode7_latch as u16) as i16;
+        self.state.mode7_latch = value;
+    }
+
+    /// Register 211F: M7X - Mode 7 center X
+    /// 15  bit  8   7  bit  0
+    ///  ---- ----   ---- ----
+    ///  ...X XXXX   XXXX XXXX
+    ///     | ||||   |||| ||||
+    ///     +-++++---++++-++++- Center X coordinate (signed)
+    ///
+    /// On write: M7X = (value << 8) | mode7_latch
+    ///           mode7_latch = value
+    fn write_m7x(&mut self, value: u8) {
+        self.state.mode7.center_x = self.latch_mode7_value(value);
+    }
+
+    /// Register 2120: M7Y - Mode 7 center Y
+    /// 15  bit  8   7  bit  0
+    ///  ---- ----   ---- ----
+    ///  ...Y YYYY   YYYY YYYY
+    ///     | ||||   |||| ||||
+    ///     +-++++---++++-++++- Center Y coordinate (signed)
+    ///
+    /// On write: M7Y = (value << 8) | mode7_latch
+    ///           mode7_latch = value
+    fn write_m7y(&mut self, value: u8) {
+        self.state.mode7.center_y = self.latch_mode7_value(value);
+    }
+
+    /// Combines `value` with the mode 7 latch into a signed 13 bit value and updates the latch.
+    fn latch_mode7_value(&mut self, value: u8) -> i16 {
+        let result = mode7::sign_extend_13(((value as u16) << 8) | self.state.mode7_latch as u16);
         self.state.mode7_latch = value;
+        result
+    }
+
+    /// Register 2133: SETINI - Screen mode/video select
+    /// 7  bit  0
+    /// ---- ----
+    /// EX.. HOiI
+    /// ||   ||||
+    /// ||   |||+- Screen interlace
+    /// ||   ||+-- OBJ interlace
+    /// ||   |+--- Overscan mode (0 = 224 lines, 1 = 239 lines)
+    /// ||   +---- High-res mode (pseudo 512 pixels)
+    /// |+-------- EXTBG mode
+    /// +--------- External sync
+    fn write_setini(&mut self, value: u8) {
+        self.state.interlace = value.bit(0);
+        self.state.obj_interlace = value.bit(1);
+        self.state.overscan = value.bit(2);
+        self.state.pseudo_hires = value.bit(3);
     }
 
     /// Register 2134-6: MPYL/M/H - 24 Bit Multipliction result
@@ -867,8 +1437,11 @@ impl Ppu {
     /// |+-------- Range over flag (sprite tile overflow)
     /// +--------- Time over flag (sprite overflow)
     fn peek_stat77(&self) -> u8 {
-        log::warn!("STAT77 not implemented");
-        0
+        // All known consoles report PPU1 version 1.
+        let mut value: u8 = 0x01;
+        value.set_bit(6, self.state.obj_range_over);
+        value.set_bit(7, self.state.obj_time_over);
+        value
     }
 
     /// Register 213F: STAT78 - PPU2 status and version number
@@ -898,29 +1471,124 @@ impl Ppu {
     }
 }
 
+/// Returns whether a write to `addr` can change the pixels of the current scanline. VRAM can only
+/// be written during vblank or forced blank, and $2134-$213F are read-only.
+fn affects_rendering(addr: AddressU24) -> bool {
+    !matches!(addr.offset, 0x2115..=0x2119 | 0x2134..=0x213F)
+}
+
+/// Scales `color` by the INIDISP master brightness, from 0 (black) to 15 (full brightness).
+/// The hardware multiplies each channel by brightness + 1 and divides by 16, except for
+/// brightness 0 which is black.
+fn apply_brightness(color: Rgb15, brightness: u8) -> Rgb15 {
+    if brightness == 0 {
+        return Rgb15(0);
+    }
+    let scale = |channel: u8| (channel as u16 * (brightness as u16 + 1) / 16) as u8;
+    let mut result = Rgb15(0);
+    result.set_r(scale(color.r()));
+    result.set_g(scale(color.g()));
+    result.set_b(scale(color.b()));
+    result
+}
+
+/// Rendered video output. The size depends on the hires, overscan and interlace settings.
 #[derive(Encode, Decode, Clone)]
-pub struct Framebuffer(Vec<Rgb15>);
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgb15>,
+}
 
 impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Rgb15(0); (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Doubles the width of the framebuffer by repeating each pixel horizontally.
+    pub fn double_width(&mut self) {
+        self.pixels = self
+            .pixels
+            .iter()
+            .flat_map(|pixel| [*pixel, *pixel])
+            .collect();
+        self.width *= 2;
+    }
+
+    /// Resizes the framebuffer, clearing its contents if the size changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if self.width != width || self.height != height {
+            *self = Self::new(width, height);
+        }
+    }
+
     fn iter(&self) -> impl Iterator<Item = (u32, u32, &Rgb15)> {
-        self.0
+        self.pixels
             .iter()
             .enumerate()
-            .map(|(idx, pixel)| (idx as u32 % 256, idx as u32 / 256, pixel))
+            .map(|(idx, pixel)| (idx as u32 % self.width, idx as u32 / self.width, pixel))
     }
 
     pub fn to_rgba<ImageT: Image>(&self) -> ImageT {
-        let mut image = ImageT::new(256, 224);
+        let mut image = ImageT::new(self.width, self.height);
         for (x, y, pixel) in self.iter() {
             image.set_pixel((x, y), (*pixel).into());
         }
         image
     }
+
+    /// Horizontal and vertical factors to stretch the frame to square pixels. Hires frames
+    /// without interlace are doubled in height, interlaced frames without hires in width.
+    pub fn aspect_scale(&self) -> (u32, u32) {
+        let hires = self.width > 256;
+        let interlace = self.height > 240;
+        match (hires, interlace) {
+            (true, false) => (1, 2),
+            (false, true) => (2, 1),
+            _ => (1, 1),
+        }
+    }
+
+    /// Renders the frame with square pixels, scaled up by an integer `scale` using nearest
+    /// neighbor.
+    pub fn to_scaled_rgba<ImageT: Image>(&self, scale: u32) -> ImageT {
+        let (x_scale, y_scale) = self.aspect_scale();
+        let (x_scale, y_scale) = (x_scale * scale, y_scale * scale);
+        let mut image = ImageT::new(self.width * x_scale, self.height * y_scale);
+        for (x, y, pixel) in self.iter() {
+            for dy in 0..y_scale {
+                for dx in 0..x_scale {
+                    image.set_pixel((x * x_scale + dx, y * y_scale + dy), (*pixel).into());
+                }
+            }
+        }
+        image
+    }
+
+    /// Saves the frame as PNG file. A `scale` of 1 keeps the native resolution, except for
+    /// hires or interlaced frames, which are stretched to keep the aspect ratio.
+    pub fn save_png(&self, path: &Path, scale: u32) -> anyhow::Result<()> {
+        self.to_scaled_rgba::<image::RgbaImage>(scale)
+            .save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
 }
 
 impl Default for Framebuffer {
     fn default() -> Self {
-        Self(vec![Rgb15(0); 256 * 224])
+        Self::new(256, 224)
     }
 }
 
@@ -928,13 +1596,13 @@ impl std::ops::Index<(u32, u32)> for Framebuffer {
     type Output = Rgb15;
 
     fn index(&self, index: (u32, u32)) -> &Self::Output {
-        &self.0[index.0 as usize + index.1 as usize * 256]
+        &self.pixels[index.0 as usize + index.1 as usize * self.width as usize]
     }
 }
 
 impl std::ops::IndexMut<(u32, u32)> for Framebuffer {
     fn index_mut(&mut self, index: (u32, u32)) -> &mut Self::Output {
-        &mut self.0[index.0 as usize + index.1 as usize * 256]
+        &mut self.pixels[index.0 as usize + index.1 as usize * self.width as usize]
     }
 }
 
@@ -957,6 +1625,38 @@ enum ColorMathOperation {
     Subtract,
 }
 
+/// Region of the color window in which CGWSEL clipping or color math prevention is applied.
+#[derive(Encode, Decode, Copy, Clone)]
+enum ColorWindowRegion {
+    Nowhere,
+    OutsideWindow,
+    InsideWindow,
+    Everywhere,
+}
+
+impl ColorWindowRegion {
+    fn applies(self, in_window: bool) -> bool {
+        match self {
+            Self::Nowhere => false,
+            Self::OutsideWindow => !in_window,
+            Self::InsideWindow => in_window,
+            Self::Everywhere => true,
+        }
+    }
+}
+
+impl From<u8> for ColorWindowRegion {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Nowhere,
+            1 => Self::OutsideWindow,
+            2 => Self::InsideWindow,
+            3 => Self::Everywhere,
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[derive(Default, Copy, Clone, Debug, Encode, Decode)]
 struct Background {
     main_enabled: bool,
@@ -979,6 +1679,13 @@ impl Background {
         coarse_y: u32,
         vram: &Vram,
     ) -> Tile<TileDecoderT> {
+        Tile::from_tilemap_entry(
+            self.tileset_addr,
+            self.tilemap_entry(coarse_x, coarse_y, vram),
+        )
+    }
+
+    fn tilemap_entry(&self, coarse_x: u32, coarse_y: u32, vram: &Vram) -> u16 {
         let tilemap_idx = match self.tilemap_size {
             TilemapSize::Size32x32 => 0,
             TilemapSize::Size64x32 => (coarse_x / 32) % 2,
@@ -986,8 +1693,7 @@ impl Background {
             TilemapSize::Size64x64 => (coarse_x / 32) % 2 + ((coarse_y / 32) % 2) * 2,
         };
         let tile_idx = tilemap_idx * 1024 + (coarse_y % 32) * 32 + (coarse_x % 32);
-        let tilemap_entry = vram[self.tilemap_addr + tile_idx];
-        Tile::from_tilemap_entry(self.tileset_addr, tilemap_entry)
+        vram[self.tilemap_addr + tile_idx]
     }
 
     fn coarse_width(&self) -> u32 {
@@ -1016,6 +1722,7 @@ enum BitDepth {
     Bpp2,
     Bpp4,
     Bpp8,
+    /// BG3 is not rendered but used as offset-per-tile table for BG1 and BG2.
     Opt,
 }
 
@@ -1073,6 +1780,18 @@ impl<TileDecoderT: TileDecoder> Tile<TileDecoderT> {
         }
     }
 
+    /// Returns the tile following this one in the tileset.
+    fn next(&self) -> Self {
+        Self {
+            tile_addr: self.tile_addr + TileDecoderT::WORDS_PER_ROW * 8,
+            palette: self.palette,
+            priority: self.priority,
+            flip_v: self.flip_v,
+            flip_h: self.flip_h,
+            _decoder: PhantomData,
+        }
+    }
+
     fn row(&self, row_idx: u32, vram: &Vram) -> TileRow<TileDecoderT> {
         let flipped_idx = if self.flip_v { 7 - row_idx } else { row_idx };
         TileRow::new(
@@ -1111,7 +1830,8 @@ impl<TileDecoderT: TileDecoder> TileRow<TileDecoderT> {
         if raw_pixel == 0 {
             0
         } else {
-            raw_pixel.saturating_add(self.palette.saturating_mul(TileDecoderT::NUM_COLORS))
+            // 8bpp tiles use all 256 colors, their palette bits are only used for direct color.
+            (raw_pixel as u16 + self.palette as u16 * TileDecoderT::NUM_COLORS) as u8
         }
     }
 
@@ -1122,7 +1842,7 @@ impl<TileDecoderT: TileDecoder> TileRow<TileDecoderT> {
 
 trait TileDecoder {
     const WORDS_PER_ROW: u32;
-    const NUM_COLORS: u8;
+    const NUM_COLORS: u16;
 
     fn new(tile_addr: AddressU15, vram: &Vram) -> Self;
     fn pixel(&self, pixel_idx: u32) -> u8;
@@ -1134,7 +1854,7 @@ struct Bpp2Decoder {
 
 impl TileDecoder for Bpp2Decoder {
     const WORDS_PER_ROW: u32 = 1;
-    const NUM_COLORS: u8 = 4;
+    const NUM_COLORS: u16 = 4;
 
     fn new(row_addr: AddressU15, vram: &Vram) -> Self {
         let data = vram[row_addr];
@@ -1154,7 +1874,7 @@ struct Bpp4Decoder {
 
 impl TileDecoder for Bpp4Decoder {
     const WORDS_PER_ROW: u32 = 2;
-    const NUM_COLORS: u8 = 16;
+    const NUM_COLORS: u16 = 16;
 
     fn new(row_addr: AddressU15, vram: &Vram) -> Self {
         let low_word = vram[row_addr];
@@ -1183,7 +1903,7 @@ struct Bpp8Decoder {
 
 impl TileDecoder for Bpp8Decoder {
     const WORDS_PER_ROW: u32 = 4;
-    const NUM_COLORS: u8 = 255;
+    const NUM_COLORS: u16 = 256;
 
     fn new(row_addr: AddressU15, vram: &Vram) -> Self {
         let word0 = vram[row_addr];
@@ -1215,3 +1935,336 @@ impl TileDecoder for Bpp8Decoder {
             + ((self.planes[7].bit(pixel_idx) as u8) << 7)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::debug_events::test::mock_collector;
+    use crate::common::image::Rgba32;
+
+    fn write_vram(ppu: &mut Ppu, addr: u16, value: u16) {
+        ppu.write(0x2115.into(), 0x80);
+        ppu.write(0x2116.into(), addr.low_byte());
+        ppu.write(0x2117.into(), addr.high_byte());
+        ppu.write(0x2118.into(), value.low_byte());
+        ppu.write(0x2119.into(), value.high_byte());
+    }
+
+    #[test]
+    fn test_offset_per_tile() {
+        let mut ppu = Ppu::new(mock_collector());
+        // Mode 2 with the BG3 tilemap at $1000
+        ppu.write(0x2105.into(), 0x02);
+        ppu.write(0x2109.into(), 0x10);
+        // BG1 horizontal scroll = 3
+        ppu.write(0x210D.into(), 0x03);
+        ppu.write(0x210D.into(), 0x00);
+
+        // Column 1: Horizontal offset $40 for BG1, vertical offset $10 for BG2
+        write_vram(&mut ppu, 0x1000, 0x2040);
+        write_vram(&mut ppu, 0x1020, 0x4010);
+
+        // The first column is never affected
+        assert_eq!(ppu.bg_offsets(BackgroundId::BG1, 0), (3, 0));
+        // Horizontal offset keeps the fine scroll of BG1HOFS
+        assert_eq!(ppu.bg_offsets(BackgroundId::BG1, 1), (0x43, 0));
+        assert_eq!(ppu.bg_offsets(BackgroundId::BG2, 1), (0, 0x10));
+        assert_eq!(ppu.bg_offsets(BackgroundId::BG1, 2), (3, 0));
+    }
+
+    #[test]
+    fn test_vram_access_during_active_display() {
+        let mut ppu = Ppu::new(mock_collector());
+        // Disable forced blank and move to the middle of the frame
+        ppu.write(0x2100.into(), 0x0F);
+        ppu.update_clock(ClockInfo {
+            v: 100,
+            ..ClockInfo::default()
+        });
+
+        // Writes are ignored, but the address is still incremented
+        write_vram(&mut ppu, 0x1000, 0x1234);
+        assert_eq!(ppu.state.vram[AddressU15(0x1000)], 0);
+
+        // Enable forced blank to allow writes again
+        ppu.write(0x2100.into(), 0x8F);
+        ppu.write(0x2118.into(), 0x34);
+        ppu.write(0x2119.into(), 0x12);
+        assert_eq!(ppu.state.vram[AddressU15(0x1000)], 0);
+        assert_eq!(ppu.state.vram[AddressU15(0x1001)], 0x1234);
+    }
+
+    #[test]
+    fn test_brightness_changes_during_frame() {
+        let mut ppu = Ppu::new(mock_collector());
+        // White backdrop
+        ppu.write(0x2121.into(), 0x00);
+        ppu.write(0x2122.into(), 0xFF);
+        ppu.write(0x2122.into(), 0x7F);
+
+        // Each scanline is drawn with the INIDISP value written before it starts.
+        for (v, inidisp) in [
+            (1, 0x0F),
+            (2, 0x07),
+            (3, 0x01),
+            (4, 0x00),
+            (5, 0x8F),
+            (6, 0x0F),
+        ] {
+            ppu.write(0x2100.into(), inidisp);
+            ppu.update_clock(ClockInfo {
+                v,
+                ..ClockInfo::default()
+            });
+        }
+
+        let line = |y: u32| ppu.framebuffer()[(128, y)];
+        assert_eq!(line(1), Rgb15(0x7FFF));
+        // 31 * 8 / 16 = 15
+        assert_eq!(line(2), Rgb15(0x3DEF));
+        // 31 * 2 / 16 = 3
+        assert_eq!(line(3), Rgb15(0x0C63));
+        // Brightness 0 and forced blank are both black
+        assert_eq!(line(4), Rgb15(0));
+        assert_eq!(line(5), Rgb15(0));
+        assert_eq!(line(6), Rgb15(0x7FFF));
+    }
+
+    #[test]
+    fn test_segmented_render_mode() {
+        let mut ppu = Ppu::new(mock_collector());
+        ppu.set_render_mode(RenderMode::Segmented);
+        ppu.write(0x2100.into(), 0x0F);
+        let clock_at = |v, hdot: u64| ClockInfo {
+            v,
+            h_counter: hdot * 4,
+            ..ClockInfo::default()
+        };
+
+        // Set the backdrop color during hblank before scanline 10
+        ppu.update_clock(clock_at(9, 290));
+        ppu.write(0x2121.into(), 0x00);
+        ppu.write(0x2122.into(), 0x1F);
+        ppu.write(0x2122.into(), 0x00);
+
+        // Reduce brightness at x = 100 of scanline 10
+        ppu.update_clock(clock_at(10, 0));
+        ppu.update_clock(clock_at(10, 122));
+        ppu.write(0x2100.into(), 0x07);
+        ppu.update_clock(clock_at(11, 0));
+        assert_eq!(ppu.framebuffer()[(99, 10)], Rgb15(0x001F));
+        assert_eq!(ppu.framebuffer()[(100, 10)], Rgb15(0x000F));
+        assert_eq!(ppu.framebuffer()[(255, 10)], Rgb15(0x000F));
+    }
+
+    #[test]
+    fn test_segmented_render_mode_mid_scanline_writes() {
+        let mut ppu = Ppu::new(mock_collector());
+        ppu.set_render_mode(RenderMode::Segmented);
+        // Add the fixed color to the black backdrop
+        ppu.write(0x2131.into(), 0x20);
+        ppu.write(0x2100.into(), 0x0F);
+        let clock_at = |v, hdot: u64| ClockInfo {
+            v,
+            h_counter: hdot * 4,
+            ..ClockInfo::default()
+        };
+        ppu.update_clock(clock_at(10, 0));
+
+        // Writes to the VRAM ports and read-only registers do not split the scanline
+        ppu.update_clock(clock_at(10, 72));
+        write_vram(&mut ppu, 0x1000, 0x1234);
+        ppu.write(0x2134.into(), 0x00);
+        assert_eq!(ppu.segment_start, 0);
+
+        // Change the fixed color to red at x = 100
+        ppu.update_clock(clock_at(10, 122));
+        ppu.write(0x2132.into(), 0x3F);
+        assert_eq!(ppu.segment_start, 100);
+        ppu.update_clock(clock_at(11, 0));
+        assert_eq!(ppu.framebuffer()[(50, 10)], Rgb15(0));
+        assert_eq!(ppu.framebuffer()[(99, 10)], Rgb15(0));
+        assert_eq!(ppu.framebuffer()[(100, 10)], Rgb15(0x001F));
+        assert_eq!(ppu.framebuffer()[(255, 10)], Rgb15(0x001F));
+    }
+
+    /// Sets up BG1 in mode 7 with a single pixel of color $03E0 at (1, 3) on a $001F backdrop.
+    fn setup_mode7_scene(ppu: &mut Ppu) {
+        // Tilemap entry (1, 0) uses tile 1, which has a pixel of color 1 at (2, 2).
+        write_vram(ppu, 0x0001, 0x0001);
+        write_vram(ppu, 64 + 2 * 8 + 2, 0x0100);
+        ppu.write(0x2122.into(), 0x1F);
+        ppu.write(0x2122.into(), 0x00);
+        ppu.write(0x2122.into(), 0xE0);
+        ppu.write(0x2122.into(), 0x03);
+
+        // Mode 7 with BG1 on the main screen, scaled by 2 and scrolled by (4, -2)
+        ppu.write(0x2105.into(), 0x07);
+        ppu.write(0x212C.into(), 0x01);
+        for (addr, value) in [
+            (0x211B, 0x0200),
+            (0x211C, 0),
+            (0x211D, 0),
+            (0x211E, 0x0200),
+            (0x211F, 0),
+            (0x2120, 0),
+            (0x210D, 4),
+            (0x210E, 0x1FFE),
+        ] {
+            ppu.write(AddressU24::from(addr), (value as u16).low_byte());
+            ppu.write(AddressU24::from(addr), (value as u16).high_byte());
+        }
+        ppu.write(0x2100.into(), 0x0F);
+    }
+
+    #[test]
+    fn test_oam_and_cgram_writes_during_active_display() {
+        let mut ppu = Ppu::new(mock_collector());
+        setup_mode7_scene(&mut ppu);
+        let clock_at = |v, hdot: u64| ClockInfo {
+            v,
+            h_counter: hdot * 4,
+            ..ClockInfo::default()
+        };
+
+        // CGRAM writes go to the color of the pixel currently drawn. On scanline 3, pixel 1
+        // shows CGRAM entry 1 and pixel 2 the backdrop.
+        ppu.update_clock(clock_at(3, 0));
+        ppu.update_clock(clock_at(3, 23));
+        ppu.write(0x2121.into(), 0x80);
+        ppu.write(0x2122.into(), 0x12);
+        ppu.write(0x2122.into(), 0x34);
+        ppu.update_clock(clock_at(3, 24));
+        ppu.write(0x2122.into(), 0x56);
+        ppu.write(0x2122.into(), 0x78);
+        assert_eq!(ppu.state.cgram[1], Rgb15(0x3412));
+        assert_eq!(ppu.state.cgram[0], Rgb15(0x7856));
+        // The CGRAM address is still incremented, writes during hblank are not redirected
+        ppu.update_clock(clock_at(3, 300));
+        ppu.write(0x2122.into(), 0x9A);
+        ppu.write(0x2122.into(), 0x0B);
+        assert_eq!(ppu.state.cgram[0x82], Rgb15(0x0B9A));
+
+        // OAM writes go to the sprite checked by range evaluation, sprite 50 at dot 100
+        ppu.update_clock(clock_at(3, 100));
+        ppu.write(0x2102.into(), 0x00);
+        ppu.write(0x2104.into(), 0x12);
+        ppu.write(0x2104.into(), 0x34);
+        assert_eq!(ppu.state.oam.get_sprite(50).x, 0x12);
+        assert_eq!(ppu.state.oam.get_sprite(50).y, 0x34);
+        assert_eq!(ppu.state.oam.get_sprite(0).x, 0);
+    }
+
+    #[test]
+    fn test_mode7() {
+        let mut ppu = Ppu::new(mock_collector());
+        setup_mode7_scene(&mut ppu);
+
+        // Plane pixel (10, 2) is shown at screen position (1, 3)
+        assert_eq!(ppu.state.mode7.transform(1, 3), (10, 2));
+        ppu.draw_scanline(3);
+        assert_eq!(ppu.framebuffer()[(0, 3)], Rgb15(0x001F));
+        assert_eq!(ppu.framebuffer()[(1, 3)], Rgb15(0x03E0));
+        assert_eq!(ppu.framebuffer()[(2, 3)], Rgb15(0x001F));
+        assert_eq!(ppu.debug().mode7_scanline(3), ppu.state.mode7);
+    }
+
+    #[test]
+    fn test_debug_overrides() {
+        let mut ppu = Ppu::new(mock_collector());
+        setup_mode7_scene(&mut ppu);
+
+        ppu.set_debug_overrides(PpuDebugOverrides {
+            hide_bg: [true, false, false, false],
+            ..Default::default()
+        });
+        ppu.draw_scanline(3);
+        assert_eq!(ppu.framebuffer()[(1, 3)], Rgb15(0x001F));
+
+        // Pixels of other layers, including the backdrop, are dimmed
+        ppu.set_debug_overrides(PpuDebugOverrides {
+            highlight: Some(DebugLayer::Background(BackgroundId::BG1)),
+            ..Default::default()
+        });
+        ppu.draw_scanline(3);
+        assert_eq!(ppu.framebuffer()[(0, 3)], Rgb15(0x0009));
+        assert_eq!(ppu.framebuffer()[(1, 3)], Rgb15(0x03E0));
+    }
+
+    #[test]
+    fn test_color_math() {
+        let mut ppu = Ppu::new(mock_collector());
+        // Add sub screen with half color math, fixed color = (4, 4, 4)
+        ppu.write(0x2130.into(), 0x02);
+        ppu.write(0x2131.into(), 0x41);
+        ppu.write(0x2132.into(), 0xE4);
+
+        let main = Rgb15(0x7FFF);
+        let sub = Rgb15(0x0421);
+        // Channels are clamped after halving
+        assert_eq!(ppu.color_math(main, true, Some(sub)), Rgb15(0x4210));
+        // Transparent sub screen pixels use the fixed color without halving
+        assert_eq!(ppu.color_math(Rgb15(0), true, None), Rgb15(0x1084));
+        assert_eq!(ppu.color_math(main, false, Some(sub)), main);
+
+        // Subtract fixed color, clamped to 0
+        ppu.write(0x2130.into(), 0x00);
+        ppu.write(0x2131.into(), 0x81);
+        assert_eq!(
+            ppu.color_math(Rgb15(0x1882), true, Some(sub)),
+            Rgb15(0x0800)
+        );
+    }
+
+    #[test]
+    fn test_direct_color() {
+        let mut ppu = Ppu::new(mock_collector());
+        // Mode 3 with BG1 on the main screen, tilemap at $1000 and tiles at $0000
+        ppu.write(0x2105.into(), 0x03);
+        ppu.write(0x2107.into(), 0x10);
+        ppu.write(0x212C.into(), 0x01);
+        // Tilemap entry 0 uses tile 1 with palette 5
+        write_vram(&mut ppu, 0x1000, 0x1401);
+        // The first pixel of tile 1 has value $9D
+        write_vram(&mut ppu, 32, 0x0080);
+        write_vram(&mut ppu, 40, 0x8080);
+        write_vram(&mut ppu, 48, 0x0080);
+        write_vram(&mut ppu, 56, 0x8000);
+        ppu.write(0x2121.into(), 0x9D);
+        ppu.write(0x2122.into(), 0x34);
+        ppu.write(0x2122.into(), 0x12);
+        ppu.write(0x2100.into(), 0x0F);
+
+        // Without direct color, the palette bits are ignored in 8bpp modes
+        ppu.draw_scanline(0);
+        assert_eq!(ppu.framebuffer()[(0, 0)], Rgb15(0x1234));
+
+        // With direct color, the palette bits extend the BBGGGRRR color to (22, 12, 20)
+        ppu.write(0x2130.into(), 0x01);
+        ppu.draw_scanline(0);
+        assert_eq!(ppu.framebuffer()[(0, 0)], Rgb15(0x5196));
+    }
+
+    #[test]
+    fn test_save_png() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frame.png");
+        let mut frame = Framebuffer::new(256, 224);
+        frame[(1, 2)] = Rgb15(0x001F);
+
+        frame.save_png(&path, 2).unwrap();
+        let image = image::open(&path).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (512, 448));
+        assert_eq!(image[(2, 4)], image[(3, 5)]);
+        assert_eq!(image[(3, 5)], image::Rgba(Rgba32::from(Rgb15(0x001F)).0));
+        assert_eq!(image[(4, 4)], image::Rgba([0, 0, 0, 255]));
+
+        // Hires without interlace is doubled in height to keep the aspect ratio
+        let hires = Framebuffer::new(512, 224);
+        hires.save_png(&path, 1).unwrap();
+        assert_eq!(image::image_dimensions(&path).unwrap(), (512, 448));
+        let interlace = Framebuffer::new(256, 478);
+        interlace.save_png(&path, 1).unwrap();
+        assert_eq!(image::image_dimensions(&path).unwrap(), (512, 478));
+    }
+}
@@ -0,0 +1,192 @@
+//! Implementation of the Mode 7 affine transformation of BG1.
+use bitcode::Decode;
+use bitcode::Encode;
+use intbits::Bits;
+
+use super::vram::Vram;
+use crate::common::address::AddressU15;
+use crate::common::uint::U16Ext;
+
+/// Size of the Mode 7 plane in pixels, in both directions.
+pub const MODE7_PLANE_SIZE: u32 = 1024;
+
+/// Mode 7 registers. These are commonly changed per scanline via HDMA.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Encode, Decode)]
+pub struct Mode7 {
+    /// Transformation matrix, signed 8.8 fixed point.
+    pub a: i16,
+    pub b: i16,
+    pub c: i16,
+    pub d: i16,
+    /// Center of the transformation on the plane, signed 13 bit.
+    pub center_x: i16,
+    pub center_y: i16,
+    /// Scroll offsets, signed 13 bit.
+    pub h_offset: i16,
+    pub v_offset: i16,
+    pub repeat: Mode7Repeat,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+/// Selects what is shown outside the 1024x1024 plane.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Encode, Decode, strum::Display)]
+pub enum Mode7Repeat {
+    /// The plane repeats infinitely.
+    #[default]
+    Wrap,
+    Transparent,
+    /// Filled with tile 0.
+    Tile0,
+}
+
+impl Mode7 {
+    /// Register 211A: M7SEL - Mode 7 settings
+    /// 7  bit  0
+    /// ---- ----
+    /// RF.. ..YX
+    /// ||     ||
+    /// ||     |+- Flip screen horizontally
+    /// ||     +-- Flip screen vertically
+    /// |+-------- Non-tilemap fill (0 = transparent, 1 = character 0)
+    /// +--------- Tilemap repeat (0 = repeat tilemap, 1 = use non-tilemap fill)
+    pub fn write_m7sel(&mut self, value: u8) {
+        self.flip_x = value.bit(0);
+        self.flip_y = value.bit(1);
+        self.repeat = match value.bits(6..=7) {
+            0 | 1 => Mode7Repeat::Wrap,
+            2 => Mode7Repeat::Transparent,
+            3 => Mode7Repeat::Tile0,
+            _ => unreachable!(),
+        };
+    }
+
+    /// Maps a screen pixel to a pixel on the plane. The result can be outside of the plane.
+    ///
+    /// Follows the hardware calculation, including the precision lost by truncating the
+    /// intermediate products.
+    pub fn transform(&self, screen_x: u32, screen_y: u32) -> (i32, i32) {
+        let screen_x = if self.flip_x {
+            255 - screen_x
+        } else {
+            screen_x
+        } as i32;
+        let screen_y = if self.flip_y {
+            255 - screen_y
+        } else {
+            screen_y
+        } as i32;
+        let (a, b, c, d) = (self.a as i32, self.b as i32, self.c as i32, self.d as i32);
+        let center_x = self.center_x as i32;
+        let center_y = self.center_y as i32;
+        let x = clip(self.h_offset as i32 - center_x);
+        let y = clip(self.v_offset as i32 - center_y);
+
+        let origin_x = ((a * x) & !63) + ((b * y) & !63) + ((b * screen_y) & !63) + (center_x << 8);
+        let origin_y = ((c * x) & !63) + ((d * y) & !63) + ((d * screen_y) & !63) + (center_y << 8);
+        (
+            (origin_x + a * screen_x) >> 8,
+            (origin_y + c * screen_x) >> 8,
+        )
+    }
+
+    /// Returns the 8bpp pixel shown at the screen position, or 0 if transparent.
+    pub fn pixel(&self, screen_x: u32, screen_y: u32, vram: &Vram) -> u8 {
+        let (x, y) = self.transform(screen_x, screen_y);
+        let size = MODE7_PLANE_SIZE as i32;
+        if (0..size).contains(&x) && (0..size).contains(&y) {
+            return plane_pixel(x as u32, y as u32, vram);
+        }
+        match self.repeat {
+            Mode7Repeat::Wrap => {
+                plane_pixel(x.rem_euclid(size) as u32, y.rem_euclid(size) as u32, vram)
+            }
+            Mode7Repeat::Transparent => 0,
+            Mode7Repeat::Tile0 => tile_pixel(0, x as u32 % 8, y as u32 % 8, vram),
+        }
+    }
+}
+
+/// Returns the pixel at (x, y) of the plane.
+///
+/// The 128x128 tilemap is stored in the low bytes of VRAM $0000-$3FFF, the 256 8bpp tiles are
+/// stored in the high bytes.
+pub fn plane_pixel(x: u32, y: u32, vram: &Vram) -> u8 {
+    let tilemap_addr = AddressU15(((y / 8) * 128 + x / 8) as u16);
+    let tile = vram[tilemap_addr].low_byte();
+    tile_pixel(tile, x % 8, y % 8, vram)
+}
+
+fn tile_pixel(tile: u8, fine_x: u32, fine_y: u32, vram: &Vram) -> u8 {
+    let pixel_addr = AddressU15(tile as u16 * 64 + (fine_y * 8 + fine_x) as u16);
+    vram[pixel_addr].high_byte()
+}
+
+/// Sign extends a 13 bit register value.
+pub fn sign_extend_13(value: u16) -> i16 {
+    ((value << 3) as i16) >> 3
+}
+
+/// Clips the scroll offset relative to the center to 10 bits plus sign, as the hardware does.
+fn clip(value: i32) -> i32 {
+    if value.bit(13) {
+        value | !0x3FF
+    } else {
+        value & 0x3FF
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> Mode7 {
+        Mode7 {
+            a: 0x100,
+            d: 0x100,
+            ..Mode7::default()
+        }
+    }
+
+    #[test]
+    fn test_transform_identity() {
+        let mode7 = identity();
+        assert_eq!(mode7.transform(0, 0), (0, 0));
+        assert_eq!(mode7.transform(10, 20), (10, 20));
+        let scrolled = Mode7 {
+            h_offset: 100,
+            v_offset: -50,
+            ..identity()
+        };
+        assert_eq!(scrolled.transform(10, 20), (110, -30));
+    }
+
+    #[test]
+    fn test_transform_scale_and_rotate() {
+        // Scale by 2 around the center (128, 112)
+        let scaled = Mode7 {
+            a: 0x200,
+            d: 0x200,
+            center_x: 128,
+            center_y: 112,
+            ..Mode7::default()
+        };
+        assert_eq!(scaled.transform(128, 112), (128, 112));
+        assert_eq!(scaled.transform(129, 113), (130, 114));
+
+        // Rotate by 90 degrees
+        let rotated = Mode7 {
+            b: 0x100,
+            c: -0x100,
+            ..Mode7::default()
+        };
+        assert_eq!(rotated.transform(10, 20), (20, -10));
+    }
+
+    #[test]
+    fn test_sign_extend_13() {
+        assert_eq!(sign_extend_13(0x0FFF), 0x0FFF);
+        assert_eq!(sign_extend_13(0x1FFF), -1);
+        assert_eq!(sign_extend_13(0xF000), -0x1000);
+    }
+}
@@ -1,6 +1,7 @@
 //! Implementation of the OAM containing sprite data.
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::ops::Range;
 
 use bitcode::Decode;
 use bitcode::Encode;
@@ -13,6 +14,12 @@ pub struct Oam {
     memory: Vec<u8>,
     /// Contains the currently selected OAM address set via the OAMADD register.
     current_addr: OamAddr,
+    /// The address last written to OAMADD. The internal address is reset to it at the start of
+    /// vblank.
+    reload_addr: OamAddr,
+    /// OAM priority rotation enabled via OAMADDH. The sprite selected by OAMADD becomes the
+    /// highest priority sprite.
+    priority_rotation: bool,
     /// Represents the write latch. Contains the previous written value or None if the latch is
     /// not set.
     latch: Option<u8>,
@@ -33,6 +40,8 @@ impl Oam {
         Self {
             memory: vec![0; 544],
             current_addr: OamAddr(0),
+            reload_addr: OamAddr(0),
+            priority_rotation: false,
             latch: None,
             sprite_sizes: (SpriteSize::Size8x8, SpriteSize::Size16x16),
             nametables: (AddressU15(0), AddressU15(0)),
@@ -57,12 +66,30 @@ impl Oam {
     /// On write: Update OAMADD
     ///           internal_oamadd = (OAMADD & $1FF) << 1
     pub fn write_oamaddl(&mut self, value: u8) {
-        self.current_addr.0.set_bit(0, false);
-        self.current_addr.0.set_bits(1..9, value as u16);
+        self.reload_addr.0.set_bits(1..9, value as u16);
+        self.current_addr = self.reload_addr;
     }
 
     pub fn write_oamaddh(&mut self, value: u8) {
-        self.current_addr.0.set_bit(9, value.bit(0));
+        self.reload_addr.0.set_bit(9, value.bit(0));
+        self.priority_rotation = value.bit(7);
+        self.current_addr = self.reload_addr;
+    }
+
+    /// Resets the internal OAM address to the value of OAMADD. Happens at the start of vblank
+    /// unless in forced blank.
+    pub fn reload_address(&mut self) {
+        self.current_addr = self.reload_addr;
+    }
+
+    /// The highest priority sprite. With priority rotation this is the sprite selected by
+    /// OAMADD bits 1-7, otherwise sprite 0.
+    pub fn first_sprite(&self) -> u32 {
+        if self.priority_rotation {
+            (self.reload_addr.0 as u32 >> 2) & 0x7F
+        } else {
+            0
+        }
     }
 
     /// 7  bit  0
@@ -110,19 +137,24 @@ impl Oam {
     ///             [internal_oamadd] = value
     ///           If internal_oamadd >= $200, [internal_oamadd] = value
     ///           internal_oamadd = internal_oamadd + 1
-    pub fn write_oamdata(&mut self, value: u8) {
+    ///
+    /// During active display, hardware writes to the address currently read by sprite evaluation
+    /// (see `evaluation_addr`). The PPU passes that address in `redirect`, the internal address
+    /// is still incremented and selects which table is written.
+    pub fn write_oamdata(&mut self, value: u8, redirect: Option<u16>) {
         if !self.current_addr.0.bit(0) {
             self.latch = Some(value);
         }
+        let addr = redirect.map_or(usize::from(self.current_addr), usize::from);
         match self.current_addr.0 {
             0..=0x1FF => {
                 if self.current_addr.0.bit(0) {
-                    self.memory[usize::from(self.current_addr) - 1] = self.latch.unwrap();
-                    self.memory[usize::from(self.current_addr)] = value;
+                    self.memory[addr & !1] = self.latch.unwrap();
+                    self.memory[addr | 1] = value;
                 }
             }
             0x200..=0x21F => {
-                self.memory[usize::from(self.current_addr)] = value;
+                self.memory[addr] = value;
             }
             _ => {}
         }
@@ -148,29 +180,81 @@ impl Oam {
         self.memory[usize::from(self.current_addr)]
     }
 
-    /// Sprites on this scanline, ordered for correct compositing: **higher OAM index first**.
+    /// The OAM address read by sprite range evaluation at `hdot` of an active scanline.
+    ///
+    /// Range evaluation checks one sprite every 2 dots during the first 256 dots, starting with
+    /// `first_sprite`. The last sprite checked is used for the rest of the scanline.
+    pub fn evaluation_addr(&self, hdot: u64) -> u16 {
+        let sprite = (self.first_sprite() + hdot.min(255) as u32 / 2) % 128;
+        sprite as u16 * 4
+    }
+
+    /// Evaluates which sprites are drawn on this scanline, following the hardware limits.
+    ///
+    /// Range evaluation walks OAM starting at `first_sprite` and collects up to 32 sprites that
+    /// are on this scanline. Any further sprite sets the range over flag.
+    ///
+    /// Time evaluation then fetches the 8x8 tiles of those sprites, starting with the last
+    /// sprite in range. Only tiles that are (partially) on screen are fetched. After 34 tiles
+    /// the time over flag is set and the remaining tiles are dropped. Note that this drops the
+    /// tiles of the highest priority sprites.
+    ///
+    /// The returned sprites are ordered lowest priority first, so the renderer can overwrite
+    /// per-pixel sprite data in order and the last writer wins.
     ///
-    /// Hardware gives **lower OAM index priority** on overlapping opaque pixels. The renderer
-    /// overwrites per-pixel sprite data in this order, so the last writer wins — we therefore
-    /// return higher indices first and lower indices last.
-    pub fn get_all_sprites_on_scanline(&self, scanline: u32) -> Vec<(Sprite, u32)> {
-        let mut sprites = Vec::new();
-        for sprite_id in 0..128 {
-            let sprite = self.get_sprite(sprite_id);
-
-            let y = sprite.y;
-            let overdraw_scanline = scanline + 256;
-            if (y..(y + sprite.height())).contains(&scanline) {
-                sprites.push((sprite, scanline - y));
-            } else if (y..(y + sprite.height())).contains(&overdraw_scanline) {
-                sprites.push((sprite, overdraw_scanline - y));
+    /// With OBJ interlace enabled, sprites cover only half their height on screen and the
+    /// returned row is in half-height units. The caller selects the actual row by field.
+    pub fn evaluate_scanline(&self, scanline: u32, interlace: bool) -> SpriteEvaluation {
+        let mut evaluation = SpriteEvaluation::default();
+
+        let first_sprite = self.first_sprite();
+        let mut in_range = Vec::with_capacity(32);
+        for offset in 0..128 {
+            let sprite = self.get_sprite((first_sprite + offset) % 128);
+            let height = if interlace {
+                sprite.height() / 2
+            } else {
+                sprite.height()
+            };
+            // Sprites wrap around from the bottom of the screen to the top.
+            let row = scanline.wrapping_sub(sprite.y) % 256;
+            if row >= height {
+                continue;
+            }
+            // Sprites at X=-256 are considered in range, even though none of their tiles are
+            // on screen.
+            let x = sprite.x as i32;
+            if x <= -(sprite.width() as i32) && x != -256 {
+                continue;
             }
-            if sprites.len() > 32 {
+            if in_range.len() == 32 {
+                evaluation.range_over = true;
                 break;
             }
+            in_range.push((sprite, row));
+        }
+
+        let mut tile_count = 0;
+        for (sprite, row) in in_range.into_iter().rev() {
+            let x = sprite.x as i32;
+            let visible_tiles: Vec<u32> = (0..sprite.coarse_width())
+                .filter(|coarse_x| (-7..256).contains(&(x + *coarse_x as i32 * 8)))
+                .collect();
+            let fetched = visible_tiles.len().min(34 - tile_count);
+            if fetched < visible_tiles.len() {
+                evaluation.time_over = true;
+            }
+            tile_count += fetched;
+            if fetched > 0 {
+                let first_tile = visible_tiles[0];
+                evaluation.sprites.push(ScanlineSprite {
+                    sprite,
+                    row,
+                    tiles: first_tile..(first_tile + fetched as u32),
+                });
+            }
         }
-        sprites.reverse();
-        sprites
+        evaluation
     }
 
     pub fn get_sprite(&self, sprite_id: u32) -> Sprite {
@@ -188,6 +272,26 @@ impl Oam {
     }
 }
 
+/// Result of the sprite evaluation of one scanline.
+#[derive(Default, Debug)]
+pub struct SpriteEvaluation {
+    /// Sprites to draw, lowest priority first.
+    pub sprites: Vec<ScanlineSprite>,
+    /// More than 32 sprites were on the scanline.
+    pub range_over: bool,
+    /// More than 34 sprite tiles were on the scanline.
+    pub time_over: bool,
+}
+
+#[derive(Debug)]
+pub struct ScanlineSprite {
+    pub sprite: Sprite,
+    /// Row of the sprite on this scanline.
+    pub row: u32,
+    /// Horizontal 8x8 tiles of the sprite that have been fetched and are drawn.
+    pub tiles: Range<u32>,
+}
+
 #[derive(Default, Clone, Copy, Debug, Encode, Decode)]
 struct OamAddr(u16);
 
@@ -332,8 +436,8 @@ mod tests {
     fn test_write_cgdata() {
         let mut oam = Oam::new();
         oam.write_oamaddl(0x42);
-        oam.write_oamdata(0x03);
-        oam.write_oamdata(0xE0);
+        oam.write_oamdata(0x03, None);
+        oam.write_oamdata(0xE0, None);
         assert_eq!(oam.memory[0x84], 0x03);
         assert_eq!(oam.memory[0x85], 0xE0);
     }
@@ -377,9 +481,10 @@ mod tests {
         oam.memory[0x202] = 0;
 
         let ids: Vec<u32> = oam
-            .get_all_sprites_on_scanline(scanline)
+            .evaluate_scanline(scanline, false)
+            .sprites
             .into_iter()
-            .map(|(s, _)| s.id)
+            .map(|s| s.sprite.id)
             .collect();
 
         let pos0 = ids.iter().position(|&id| id == 0).expect("sprite 0");
@@ -389,4 +494,82 @@ mod tests {
             "expected sprite 10 before sprite 0 in decode order, got {ids:?}"
         );
     }
+
+    fn write_sprite(oam: &mut Oam, sprite_id: usize, x: u8, y: u8) {
+        oam.memory[sprite_id * 4..sprite_id * 4 + 4].copy_from_slice(&[x, y, 0, 0]);
+    }
+
+    #[test]
+    fn test_range_over() {
+        let mut oam = Oam::new();
+        for sprite_id in 0..40 {
+            write_sprite(&mut oam, sprite_id, 0, 100);
+        }
+        let evaluation = oam.evaluate_scanline(100, false);
+        assert!(evaluation.range_over);
+        assert!(!evaluation.time_over);
+        assert_eq!(evaluation.sprites.len(), 32);
+        assert_eq!(evaluation.sprites.first().unwrap().sprite.id, 31);
+        assert_eq!(evaluation.sprites.last().unwrap().sprite.id, 0);
+    }
+
+    #[test]
+    fn test_time_over() {
+        let mut oam = Oam::new();
+        // 18 sprites of 16x16 need 36 tiles.
+        oam.write_objsel(0x00);
+        oam.memory[0x200..0x205].fill(0xAA);
+        for sprite_id in 0..18 {
+            write_sprite(&mut oam, sprite_id, 0, 100);
+        }
+        let evaluation = oam.evaluate_scanline(100, false);
+        assert!(!evaluation.range_over);
+        assert!(evaluation.time_over);
+        // Tiles are fetched starting with the lowest priority sprite, so sprite 0 is dropped.
+        assert_eq!(evaluation.sprites.len(), 17);
+        assert_eq!(evaluation.sprites.last().unwrap().sprite.id, 1);
+        assert_eq!(evaluation.sprites.last().unwrap().tiles, 0..2);
+    }
+
+    #[test]
+    fn test_offscreen_tiles_are_not_fetched() {
+        let mut oam = Oam::new();
+        oam.write_objsel(0x00);
+        oam.memory[0x200] = 0x03; // Sprite 0 is 16x16 with X=-8
+        write_sprite(&mut oam, 0, 0xF8, 100);
+        write_sprite(&mut oam, 1, 250, 100);
+        oam.memory[0x200] |= 0x08; // Sprite 1 is 16x16
+        let evaluation = oam.evaluate_scanline(100, false);
+        let tiles: Vec<_> = evaluation.sprites.iter().map(|s| s.tiles.clone()).collect();
+        assert_eq!(tiles, vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn test_priority_rotation() {
+        let mut oam = Oam::new();
+        write_sprite(&mut oam, 5, 10, 100);
+        write_sprite(&mut oam, 10, 10, 100);
+        // Select sprite 10 as highest priority (OAM word address 20).
+        oam.write_oamaddl(20);
+        oam.write_oamaddh(0x80);
+        assert_eq!(oam.first_sprite(), 10);
+        let ids: Vec<u32> = oam
+            .evaluate_scanline(100, false)
+            .sprites
+            .iter()
+            .map(|s| s.sprite.id)
+            .collect();
+        assert_eq!(ids, vec![5, 10]);
+
+        // Without rotation, sprite 5 has priority again.
+        oam.write_oamaddh(0x00);
+        assert_eq!(oam.first_sprite(), 0);
+        let ids: Vec<u32> = oam
+            .evaluate_scanline(100, false)
+            .sprites
+            .iter()
+            .map(|s| s.sprite.id)
+            .collect();
+        assert_eq!(ids, vec![10, 5]);
+    }
 }
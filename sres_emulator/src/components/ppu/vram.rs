@@ -10,9 +10,12 @@ use crate::common::uint::U16Ext;
 pub struct Vram {
     memory: Vec<u16>,
     current_addr: AddressU15,
-    read_latch: bool,
+    /// Prefetched word returned by VMDATAREAD. It is loaded when VMADD is written and after each
+    /// read that increments the address.
+    read_buffer: u16,
     increment_mode: bool,
     increment_amount: u16,
+    remapping: AddressRemapping,
 }
 
 impl Vram {
@@ -21,9 +24,10 @@ impl Vram {
         Self {
             memory: vec![0; 0x20000],
             current_addr: AddressU15(0),
-            read_latch: false,
+            read_buffer: 0,
             increment_mode: false,
             increment_amount: 1,
+            remapping: AddressRemapping::None,
         }
     }
 
@@ -54,78 +58,124 @@ impl Vram {
             3 => self.increment_amount = 128,
             _ => unreachable!(),
         }
-        match value.bits(2..=3) {
-            0 => (),
-            1 => log::error!("Address remapping: 2bpp"),
-            2 => log::error!("Address remapping: 4bpp"),
-            3 => log::error!("Address remapping: 8bpp"),
+        self.remapping = match value.bits(2..=3) {
+            0 => AddressRemapping::None,
+            1 => AddressRemapping::Bpp2,
+            2 => AddressRemapping::Bpp4,
+            3 => AddressRemapping::Bpp8,
             _ => unreachable!(),
-        }
+        };
+    }
+
+    /// The VRAM address accessed by VMDATA, which is VMADD translated by the address remapping.
+    fn translated_addr(&self) -> AddressU15 {
+        let addr = self.current_addr.0;
+        AddressU15(match self.remapping {
+            AddressRemapping::None => addr,
+            AddressRemapping::Bpp2 => (addr & 0xFF00) | (addr.bits(0..=4) << 3) | addr.bits(5..=7),
+            AddressRemapping::Bpp4 => (addr & 0xFE00) | (addr.bits(0..=5) << 3) | addr.bits(6..=8),
+            AddressRemapping::Bpp8 => (addr & 0xFC00) | (addr.bits(0..=6) << 3) | addr.bits(7..=9),
+        })
+    }
+
+    /// Reloads the read buffer from the current address, then increments the address.
+    fn prefetch_and_increment(&mut self) {
+        self.read_buffer = self.memory[usize::from(self.translated_addr())];
+        self.current_addr = self.current_addr + self.increment_amount;
     }
 
     /// Register 2116: VMADDL - VRAM word address low
+    ///
+    /// On write: Update VMADD
+    ///           vram_latch = [remapped(VMADD)]
     pub fn write_vmaddl(&mut self, value: u8) {
         self.current_addr.set_low_byte(value);
-        self.read_latch = true;
+        self.read_buffer = self.memory[usize::from(self.translated_addr())];
     }
 
     /// Register 2117: VMADDH - VRAM word address high
+    ///
+    /// See `write_vmaddl`.
     pub fn write_vmaddh(&mut self, value: u8) {
         self.current_addr.set_high_byte(value.bits(0..=6));
-        self.read_latch = true;
+        self.read_buffer = self.memory[usize::from(self.translated_addr())];
     }
 
     /// Register 2118: VMDATAL - VRAM data write low
-    pub fn write_vmdatal(&mut self, value: u8) {
-        self.memory[usize::from(self.current_addr)].set_low_byte(value);
+    ///
+    /// If `access_allowed` is false (during active display), the write is ignored but the address
+    /// is still incremented.
+    pub fn write_vmdatal(&mut self, value: u8, access_allowed: bool) {
+        if access_allowed {
+            let addr = self.translated_addr();
+            self.memory[usize::from(addr)].set_low_byte(value);
+        }
         if !self.increment_mode {
             self.current_addr = self.current_addr + self.increment_amount;
         }
     }
 
     /// Register 2119: VMDATAH - VRAM data write high
-    pub fn write_vmdatah(&mut self, value: u8) {
-        self.memory[usize::from(self.current_addr)].set_high_byte(value);
+    ///
+    /// See `write_vmdatal` for `access_allowed`.
+    pub fn write_vmdatah(&mut self, value: u8, access_allowed: bool) {
+        if access_allowed {
+            let addr = self.translated_addr();
+            self.memory[usize::from(addr)].set_high_byte(value);
+        }
         if self.increment_mode {
             self.current_addr = self.current_addr + self.increment_amount;
         }
     }
 
     /// Register 2139: VMDATALREAD - VRAM data read low
+    ///
+    /// On read: value = vram_latch.low
+    ///          If increment_mode == 0:
+    ///            vram_latch = [remapped(VMADD)]
+    ///            VMADD = VMADD + increment_amount
     pub fn read_vmdatalread(&mut self) -> u8 {
         let value = self.peek_vmdatalread();
         if !self.increment_mode {
-            if self.read_latch {
-                self.read_latch = false;
-            } else {
-                self.current_addr = self.current_addr + self.increment_amount;
-            }
+            self.prefetch_and_increment();
         }
         value
     }
 
     pub fn peek_vmdatalread(&self) -> u8 {
-        self.memory[usize::from(self.current_addr)].low_byte()
+        self.read_buffer.low_byte()
     }
 
     /// Register 213A: VMDATAHREAD - VRAM data read high
+    ///
+    /// On read: value = vram_latch.high
+    ///          If increment_mode == 1:
+    ///            vram_latch = [remapped(VMADD)]
+    ///            VMADD = VMADD + increment_amount
     pub fn read_vmdatahread(&mut self) -> u8 {
         let value = self.peek_vmdatahread();
         if self.increment_mode {
-            if self.read_latch {
-                self.read_latch = false;
-            } else {
-                self.current_addr = self.current_addr + self.increment_amount;
-            }
+            self.prefetch_and_increment();
         }
         value
     }
 
     pub fn peek_vmdatahread(&self) -> u8 {
-        self.memory[usize::from(self.current_addr)].high_byte()
+        self.read_buffer.high_byte()
     }
 }
 
+/// VMAIN address remapping. Rotates the low bits of the address so that bitmap style data can be
+/// uploaded as consecutive words into tiles of the given bit depth.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Encode, Decode)]
+enum AddressRemapping {
+    #[default]
+    None,
+    Bpp2,
+    Bpp4,
+    Bpp8,
+}
+
 impl std::ops::Index<AddressU15> for Vram {
     type Output = u16;
 
@@ -133,3 +183,64 @@ impl std::ops::Index<AddressU15> for Vram {
         &self.memory[usize::from(index)]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_remapping() {
+        let mut vram = Vram::new();
+        // 2bpp: rrrrrrrr YYYccccc -> rrrrrrrr cccccYYY
+        vram.write_vmain(0x04);
+        vram.write_vmaddl(0x21);
+        vram.write_vmaddh(0x01);
+        assert_eq!(vram.translated_addr().0, 0x0109);
+        // 4bpp: rrrrrrrY YYcccccP -> rrrrrrrc ccccPYYY
+        vram.write_vmain(0x08);
+        vram.write_vmaddl(0x41);
+        vram.write_vmaddh(0x03);
+        assert_eq!(vram.translated_addr().0, 0x020D);
+        // 8bpp: rrrrrrYY YcccccPP -> rrrrrrcc cccPPYYY
+        vram.write_vmain(0x0C);
+        vram.write_vmaddl(0x83);
+        vram.write_vmaddh(0x07);
+        assert_eq!(vram.translated_addr().0, 0x041F);
+    }
+
+    #[test]
+    fn test_remapped_write() {
+        let mut vram = Vram::new();
+        // Write the first two rows of 2bpp tiles 0 and 1 as a bitmap.
+        vram.write_vmain(0x84);
+        vram.write_vmaddl(0x00);
+        vram.write_vmaddh(0x00);
+        for value in [0x1111, 0x2222] {
+            vram.write_vmdatal(value as u8, true);
+            vram.write_vmdatah((value >> 8) as u8, true);
+        }
+        vram.write_vmaddl(0x20);
+        vram.write_vmdatal(0x33, true);
+        vram.write_vmdatah(0x33, true);
+        assert_eq!(vram[AddressU15(0x0000)], 0x1111);
+        assert_eq!(vram[AddressU15(0x0008)], 0x2222);
+        assert_eq!(vram[AddressU15(0x0001)], 0x3333);
+    }
+
+    #[test]
+    fn test_read_prefetch() {
+        let mut vram = Vram::new();
+        vram.memory[0x0000] = 0x1111;
+        vram.memory[0x0008] = 0x2222;
+        vram.memory[0x0010] = 0x3333;
+        vram.write_vmain(0x84);
+        vram.write_vmaddl(0x00);
+        vram.write_vmaddh(0x00);
+        // The first read returns the word prefetched when setting the address, and prefetches it
+        // again before incrementing.
+        assert_eq!(vram.read_vmdatahread(), 0x11);
+        assert_eq!(vram.read_vmdatahread(), 0x11);
+        assert_eq!(vram.read_vmdatahread(), 0x22);
+        assert_eq!(vram.read_vmdatahread(), 0x33);
+    }
+}
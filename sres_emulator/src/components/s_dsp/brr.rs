@@ -2,10 +2,17 @@
 #![allow(dead_code)]
 
 use std::collections::VecDeque;
+use std::io::Cursor;
+use std::path::Path;
 
+use anyhow::Result;
 use bilge::prelude::*;
+use hound::WavWriter;
 use intbits::Bits;
 
+use super::WAV_SPEC;
+use crate::apu::APU_SAMPLE_RATE;
+
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct BrrDecoder {
     buffer: [i16; 2],
@@ -14,6 +21,8 @@ pub struct BrrDecoder {
     current_block: VecDeque<i16>,
     loop_addr: Option<usize>,
     last_block_header: Option<BrrBlockHeader>,
+    /// Set when a block with the end flag is loaded, see [BrrDecoder::take_end_reached].
+    end_reached: bool,
 }
 
 impl BrrDecoder {
@@ -25,6 +34,7 @@ impl BrrDecoder {
             current_block: VecDeque::with_capacity(16),
             loop_addr: None,
             last_block_header: None,
+            end_reached: false,
         }
     }
 
@@ -69,6 +79,7 @@ impl BrrDecoder {
             // Store header if this is an end block
             if block.header.end() {
                 self.last_block_header = Some(block.header);
+                self.end_reached = true;
             }
 
             let samples = self.decode(&block);
@@ -78,6 +89,19 @@ impl BrrDecoder {
         self.current_block.pop_front()
     }
 
+    /// Returns true if the current block has the end flag but not the loop flag.
+    pub fn end_without_loop(&self) -> bool {
+        self.end
+            && self
+                .last_block_header
+                .is_some_and(|header| !header.loop_flag())
+    }
+
+    /// Returns true if a block with the end flag was loaded since the last call.
+    pub fn take_end_reached(&mut self) -> bool {
+        std::mem::take(&mut self.end_reached)
+    }
+
     fn decode_bytes(&mut self, raw_block: &[u8; 9]) -> [i16; 16] {
         self.decode(&BrrBlock::from_bytes(raw_block))
     }
@@ -110,6 +134,104 @@ impl BrrDecoder {
     }
 }
 
+/// A sample referenced by the sample directory, decoded up to its end block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrrSample {
+    pub source: u8,
+    pub start_addr: u16,
+    pub loop_addr: u16,
+    pub samples: Vec<i16>,
+    /// Index of the sample at `loop_addr`, if the sample loops back into itself.
+    pub loop_start: Option<usize>,
+}
+
+impl BrrSample {
+    /// Returns None if there is no end block before the end of memory.
+    pub fn decode(memory: &[u8], source: u8, start_addr: u16, loop_addr: u16) -> Option<Self> {
+        let mut decoder = BrrDecoder::new(start_addr as usize);
+        let mut samples = Vec::new();
+        let mut addr = start_addr as usize;
+        let header = loop {
+            let block = memory.get(addr..addr + 9)?.try_into().unwrap();
+            samples.extend(decoder.decode_bytes(block));
+            addr += 9;
+            if decoder.end {
+                break BrrBlockHeader::from(block[0]);
+            }
+        };
+
+        let loop_offset = (loop_addr as usize).wrapping_sub(start_addr as usize);
+        let loops_into_sample = loop_offset < addr - start_addr as usize && loop_offset % 9 == 0;
+        let loop_start = if header.loop_flag() && loops_into_sample {
+            Some(loop_offset / 9 * 16)
+        } else {
+            None
+        };
+        Some(Self {
+            source,
+            start_addr,
+            loop_addr,
+            samples,
+            loop_start,
+        })
+    }
+
+    /// Returns the sample, with the loop repeated until it is at least `min_len` samples long.
+    pub fn playback(&self, min_len: usize) -> Vec<i16> {
+        let mut output = self.samples.clone();
+        if let Some(loop_start) = self.loop_start {
+            let loop_samples = &self.samples[loop_start..];
+            while output.len() < min_len {
+                output.extend_from_slice(loop_samples);
+            }
+        }
+        output
+    }
+
+    /// Saves the sample as WAV. Loop points are stored in a `smpl` chunk, which is understood
+    /// by most samplers and audio editors.
+    pub fn save_wav(&self, path: &Path) -> Result<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut cursor, WAV_SPEC)?;
+        for sample in &self.samples {
+            writer.write_sample(*sample)?;
+        }
+        writer.finalize()?;
+        let mut data = cursor.into_inner();
+
+        if let Some(loop_start) = self.loop_start {
+            let loop_end = self.samples.len() - 1;
+            let fields: [u32; 15] = [
+                0,                               // Manufacturer
+                0,                               // Product
+                1_000_000_000 / APU_SAMPLE_RATE, // Sample period in nanoseconds
+                60,                              // MIDI unity note
+                0,                               // MIDI pitch fraction
+                0,                               // SMPTE format
+                0,                               // SMPTE offset
+                1,                               // Number of loops
+                0,                               // Sampler data
+                0,                               // Loop: Cue point ID
+                0,                               // Loop: Forward loop
+                loop_start as u32,               // Loop: First sample
+                loop_end as u32,                 // Loop: Last sample, inclusive
+                0,                               // Loop: Fraction
+                0,                               // Loop: Infinite play count
+            ];
+            data.extend_from_slice(b"smpl");
+            data.extend_from_slice(&(fields.len() as u32 * 4).to_le_bytes());
+            for field in fields {
+                data.extend_from_slice(&field.to_le_bytes());
+            }
+            // Update the size of the RIFF chunk
+            let riff_size = data.len() as u32 - 8;
+            data[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        }
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
 pub struct BrrIterator<'a> {
     decoder: &'a mut BrrDecoder,
     memory: &'a [u8],
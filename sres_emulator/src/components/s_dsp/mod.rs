@@ -5,12 +5,41 @@ mod pitch;
 mod test;
 pub mod voice;
 
+use std::path::Path;
+
+use anyhow::Result;
 use bilge::prelude::*;
+use hound::WavSpec;
+use hound::WavWriter;
 use intbits::Bits;
 
+pub use self::brr::BrrSample;
+use self::voice::dir_entry;
 use self::voice::Voice;
+use crate::apu::APU_SAMPLE_RATE;
 use crate::common::uint::U8Ext;
 
+/// Format of WAV files written from the DSP output, which is mono.
+pub const WAV_SPEC: WavSpec = WavSpec {
+    channels: 1,
+    sample_rate: APU_SAMPLE_RATE,
+    bits_per_sample: 16,
+    sample_format: hound::SampleFormat::Int,
+};
+
+/// Selects how the DSP is advanced by the APU.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DspTiming {
+    /// A whole sample is generated at once every 32 SPC700 cycles. Register reads return the
+    /// state after the last generated sample.
+    #[default]
+    Sample,
+    /// The DSP is stepped with every SPC700 cycle following the 32 cycle schedule of the
+    /// hardware, see [SDsp::step_cycle]. Registers such as ENVX, OUTX and ENDX update at the same
+    /// cycle as on the hardware. Slower, but some sound drivers sync to these registers.
+    Cycle,
+}
+
 pub struct SDsp {
     raw: [u8; 128],
     voices: [Voice; 8],
@@ -18,13 +47,108 @@ pub struct SDsp {
     flg: Flg,
     noise_generator: NoiseGenerator,
     global_counter: u16,
+    /// ENDX: Set for each voice that reached a BRR block with the end flag.
+    endx: u8,
+    timing: DspTiming,
+    cycle_state: CycleState,
+    voice_overrides: VoiceOverrides,
+    voice_capture: Option<VoiceCapture>,
+}
+
+/// Debug overrides to listen to individual voices. Each field is a mask with one bit per voice.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VoiceOverrides {
+    /// Voices that are removed from the output.
+    pub mute: u8,
+    /// If any bit is set, only these voices are audible.
+    pub solo: u8,
+}
+
+impl VoiceOverrides {
+    pub fn is_audible(&self, voice: usize) -> bool {
+        !self.mute.bit(voice) && (self.solo == 0 || self.solo.bit(voice))
+    }
+}
+
+/// Longest voice capture, 5 minutes. A capture takes 16 bytes per sample for all 8 voices, so
+/// about 30 MB per minute.
+pub const MAX_VOICE_CAPTURE_SAMPLES: usize = APU_SAMPLE_RATE as usize * 60 * 5;
+
+/// Output of each voice after volume, recorded before [VoiceOverrides] are applied.
+///
+/// Recording stops after [MAX_VOICE_CAPTURE_SAMPLES].
+#[derive(Default, Clone)]
+pub struct VoiceCapture {
+    voices: [Vec<i16>; 8],
+}
+
+impl VoiceCapture {
+    pub fn samples(&self, voice: usize) -> &[i16] {
+        &self.voices[voice]
+    }
+
+    pub fn save_wav(&self, voice: usize, path: &Path) -> Result<()> {
+        let mut writer = WavWriter::create(path, WAV_SPEC)?;
+        for sample in &self.voices[voice] {
+            writer.write_sample(*sample)?;
+        }
+        writer.finalize()?;
+        Ok(())
+    }
+}
+
+/// State of the cycle-stepped DSP that is carried between steps of a sample period.
+#[derive(Default)]
+struct CycleState {
+    /// Step within the 32 cycle sample period.
+    step: u8,
+    /// KON is only processed every other sample.
+    every_other_sample: bool,
+    /// Voices written to KON that have not been keyed on yet.
+    new_kon: u8,
+    /// Registers latched during the misc steps.
+    kon: u8,
+    koff: u8,
+    pmon: u8,
+    non: u8,
+    dir: u8,
+    noise_bits: u16,
+    /// Voices that reached an end block during V3, added to the ENDX buffer during V4.
+    brr_end: u8,
+    /// Copied to ENDX at the V7 step of each voice.
+    endx_buffer: u8,
+    /// Sum of the voice outputs since the last output sample.
+    mix: i16,
+}
+
+/// Registers that control the processing of all voices.
+struct VoiceInputs {
+    dir: usize,
+    noise_on: u8,
+    pitch_modulation: u8,
+    key_off: u8,
+    noise_bits: u16,
 }
 
+// Steps of the sample period at which each voice is handled, following the schedule
+// implemented by blargg's SPC_DSP.
+/// V3: Decoding, interpolation and envelope of each voice.
+const VOICE_V3_STEP: [u8; 8] = [30, 1, 4, 7, 10, 13, 16, 19];
+/// V4: Sets the end flag of the voice in the ENDX buffer.
+const VOICE_V4_STEP: [u8; 8] = [31, 2, 5, 8, 11, 14, 17, 20];
+/// V7: Writes the ENDX register.
+const VOICE_V7_STEP: [u8; 8] = [2, 5, 8, 11, 14, 17, 20, 23];
+/// V8: Writes the OUTX register of the voice.
+const VOICE_V8_STEP: [u8; 8] = [3, 6, 9, 12, 15, 18, 21, 24];
+/// V9: Writes the ENVX register of the voice.
+const VOICE_V9_STEP: [u8; 8] = [4, 7, 10, 13, 16, 19, 22, 25];
+
 impl SDsp {
     pub fn read_register(&self, reg: u8) -> u8 {
         match reg {
             0x5D => self.dir,
             0x6C => self.flg.value,
+            0x7C => self.endx,
             reg => match reg.low_nibble() {
                 0x0..=0x9 => {
                     self.voices[reg.high_nibble() as usize].read_register(reg.low_nibble())
@@ -42,45 +166,209 @@ impl SDsp {
                 0x0..=0x9 => {
                     self.voices[reg.high_nibble() as usize].write_register(reg.low_nibble(), value)
                 }
-                0xC => match reg.high_nibble() {
-                    0x4 => {
-                        for (idx, voice) in self.voices.iter_mut().enumerate() {
-                            voice.trigger_on = value.bit(idx);
+                0xC => {
+                    self.raw[reg as usize] = value;
+                    match reg.high_nibble() {
+                        0x4 => match self.timing {
+                            DspTiming::Sample => {
+                                for (idx, voice) in self.voices.iter_mut().enumerate() {
+                                    voice.trigger_on = value.bit(idx);
+                                }
+                            }
+                            DspTiming::Cycle => self.cycle_state.new_kon = value,
+                        },
+                        // Any write to ENDX clears all bits
+                        0x7 => {
+                            self.endx = 0;
+                            self.cycle_state.endx_buffer = 0;
                         }
+                        _ => {}
                     }
-                    _ => {}
-                },
+                }
                 _ => self.raw[reg as usize] = value,
             },
         }
     }
 
-    pub fn generate_sample(&mut self, memory: &[u8]) -> i16 {
-        // Get the current noise bits from the noise generator
-        let noise_bits = self.noise_generator.generate(self.flg.noise_frequency());
+    /// Restores all registers, e.g. from an .spc file. Unlike [SDsp::write_register] this
+    /// restores ENDX instead of clearing it.
+    pub fn load_registers(&mut self, registers: &[u8; 128]) {
+        for (reg, value) in registers.iter().enumerate() {
+            self.write_register(reg as u8, *value);
+        }
+        self.endx = registers[0x7C];
+        self.cycle_state.endx_buffer = registers[0x7C];
+    }
 
-        let directory_offset = (self.dir as usize) * 0x100;
-        let noise_on = self.raw[0x3D]; // NON register
-        let result = self
-            .voices
-            .iter_mut()
-            .enumerate()
-            .map(|(i, v)| {
-                let use_noise = noise_on.bit(i);
-                v.generate_sample_with_noise(
-                    memory,
-                    directory_offset,
-                    use_noise,
-                    noise_bits,
-                    self.global_counter,
-                )
-            })
-            .fold(0i16, |acc, x| acc.saturating_add(x));
+    pub fn timing(&self) -> DspTiming {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: DspTiming) {
+        self.timing = timing;
+        self.cycle_state = CycleState {
+            endx_buffer: self.endx,
+            ..Default::default()
+        };
+    }
+
+    pub fn voice_overrides(&self) -> VoiceOverrides {
+        self.voice_overrides
+    }
+
+    pub fn set_voice_overrides(&mut self, overrides: VoiceOverrides) {
+        self.voice_overrides = overrides;
+    }
+
+    /// Starts recording the output of each voice, replacing any running capture.
+    pub fn start_voice_capture(&mut self) {
+        self.voice_capture = Some(VoiceCapture::default());
+    }
+
+    pub fn stop_voice_capture(&mut self) -> Option<VoiceCapture> {
+        self.voice_capture.take()
+    }
+
+    pub fn is_capturing_voices(&self) -> bool {
+        self.voice_capture.is_some()
+    }
+
+    /// Generates a whole sample at once, used with [DspTiming::Sample].
+    pub fn generate_sample(&mut self, memory: &[u8]) -> i16 {
+        let inputs = VoiceInputs {
+            dir: self.dir as usize * 0x100,
+            noise_on: self.raw[0x3D],
+            pitch_modulation: self.raw[0x2D],
+            key_off: self.raw[0x5C],
+            noise_bits: self.noise_generator.generate(self.flg.noise_frequency()),
+        };
+        let mut result = 0i16;
+        for i in 0..self.voices.len() {
+            if self.voices[i].trigger_on {
+                self.endx.set_bit(i, false);
+            }
+            let sample = self.process_voice(i, memory, &inputs);
+            let voice = &mut self.voices[i];
+            voice.latch_envx();
+            voice.latch_outx();
+            if voice.take_brr_end() {
+                self.endx.set_bit(i, true);
+            }
+            result = result.saturating_add(self.mix_voice(i, sample));
+        }
 
         self.global_counter = self.global_counter.wrapping_add(1);
         result
     }
 
+    /// Advances the DSP by one SPC700 cycle, used with [DspTiming::Cycle]. Returns the output
+    /// sample once per 32 cycle sample period.
+    ///
+    /// Voices are processed in their V3 step of the hardware schedule, their registers are
+    /// updated in later steps. KON and KOFF are only latched every other sample.
+    pub fn step_cycle(&mut self, memory: &[u8]) -> Option<i16> {
+        let step = self.cycle_state.step;
+        let mut output = None;
+        match step {
+            27 => self.cycle_state.pmon = self.raw[0x2D],
+            28 => {
+                self.cycle_state.non = self.raw[0x3D];
+                self.cycle_state.dir = self.dir;
+            }
+            29 => {
+                let state = &mut self.cycle_state;
+                state.every_other_sample = !state.every_other_sample;
+                if state.every_other_sample {
+                    state.new_kon &= !state.kon;
+                }
+                output = Some(std::mem::take(&mut state.mix));
+            }
+            30 => {
+                let state = &mut self.cycle_state;
+                if state.every_other_sample {
+                    state.kon = state.new_kon;
+                    state.koff = self.raw[0x5C];
+                    for (i, voice) in self.voices.iter_mut().enumerate() {
+                        if state.kon.bit(i) {
+                            voice.trigger_on = true;
+                            state.endx_buffer.set_bit(i, false);
+                        }
+                    }
+                }
+                state.noise_bits = self.noise_generator.generate(self.flg.noise_frequency());
+                self.global_counter = self.global_counter.wrapping_add(1);
+            }
+            _ => {}
+        }
+
+        for i in 0..self.voices.len() {
+            if step == VOICE_V3_STEP[i] {
+                let inputs = VoiceInputs {
+                    dir: self.cycle_state.dir as usize * 0x100,
+                    noise_on: self.cycle_state.non,
+                    pitch_modulation: self.cycle_state.pmon,
+                    key_off: self.cycle_state.koff,
+                    noise_bits: self.cycle_state.noise_bits,
+                };
+                let sample = self.process_voice(i, memory, &inputs);
+                if self.voices[i].take_brr_end() {
+                    self.cycle_state.brr_end.set_bit(i, true);
+                }
+                let sample = self.mix_voice(i, sample);
+                self.cycle_state.mix = self.cycle_state.mix.saturating_add(sample);
+            }
+            if step == VOICE_V4_STEP[i] && self.cycle_state.brr_end.bit(i) {
+                self.cycle_state.brr_end.set_bit(i, false);
+                self.cycle_state.endx_buffer.set_bit(i, true);
+            }
+            if step == VOICE_V7_STEP[i] {
+                self.endx = self.cycle_state.endx_buffer;
+            }
+            if step == VOICE_V8_STEP[i] {
+                self.voices[i].latch_outx();
+            }
+            if step == VOICE_V9_STEP[i] {
+                self.voices[i].latch_envx();
+            }
+        }
+
+        self.cycle_state.step = (step + 1) % 32;
+        output
+    }
+
+    fn process_voice(&mut self, i: usize, memory: &[u8], inputs: &VoiceInputs) -> i16 {
+        // Voice 0 cannot be modulated as there is no previous voice.
+        let modulation =
+            (i > 0 && inputs.pitch_modulation.bit(i)).then(|| self.voices[i - 1].output);
+        let voice = &mut self.voices[i];
+        // KOFF is not cleared by the DSP, voices stay in release while their bit is set.
+        if inputs.key_off.bit(i) {
+            voice.trigger_off = true;
+        }
+        voice.process_sample(
+            memory,
+            inputs.dir,
+            inputs.noise_on.bit(i),
+            inputs.noise_bits,
+            self.global_counter,
+            modulation,
+        )
+    }
+
+    /// Records the output of voice `i` and returns its contribution to the mix.
+    fn mix_voice(&mut self, i: usize, sample: i16) -> i16 {
+        if let Some(capture) = &mut self.voice_capture {
+            if capture.voices[i].len() < MAX_VOICE_CAPTURE_SAMPLES {
+                capture.voices[i].push(sample);
+            }
+        }
+        if self.voice_overrides.is_audible(i) {
+            sample
+        } else {
+            0
+        }
+    }
+
     pub fn debug(&self) -> SDspDebug<'_> {
         SDspDebug(self)
     }
@@ -104,6 +392,11 @@ impl Default for SDsp {
             flg: Flg::default(),
             noise_generator: NoiseGenerator::new(),
             global_counter: 0,
+            endx: 0,
+            timing: DspTiming::default(),
+            cycle_state: CycleState::default(),
+            voice_overrides: VoiceOverrides::default(),
+            voice_capture: None,
         }
     }
 }
@@ -138,6 +431,43 @@ impl SDspDebug<'_> {
     pub fn key_off(&self) -> u8 {
         self.0.raw[0x5C]
     }
+
+    pub fn pitch_modulation(&self) -> u8 {
+        self.0.raw[0x2D]
+    }
+
+    pub fn end_flags(&self) -> u8 {
+        self.0.endx
+    }
+
+    pub fn voice_overrides(&self) -> VoiceOverrides {
+        self.0.voice_overrides
+    }
+
+    pub fn is_capturing_voices(&self) -> bool {
+        self.0.is_capturing_voices()
+    }
+
+    /// Decodes the samples selected by the SRCN register of each voice. The sample directory has
+    /// no size, so other entries cannot be told apart from unrelated data.
+    pub fn samples(&self, memory: &[u8]) -> Vec<BrrSample> {
+        let dir = self.0.dir as usize * 0x100;
+        let mut sources: Vec<u8> = self
+            .0
+            .voices
+            .iter()
+            .map(|voice| voice.sample_source)
+            .collect();
+        sources.sort();
+        sources.dedup();
+        sources
+            .into_iter()
+            .filter_map(|source| {
+                let (start_addr, loop_addr) = dir_entry(memory, dir, source);
+                BrrSample::decode(memory, source, start_addr, loop_addr)
+            })
+            .collect()
+    }
 }
 
 // Flg register
@@ -41,12 +41,17 @@ impl PitchGenerator {
             GAUSSIAN_TABLE[0x100 + fractional],
             GAUSSIAN_TABLE[fractional],
         ];
-        let samples = (0..4).map(|i| {
+        let samples: [i32; 4] = std::array::from_fn(|i| {
             let sample_index = (self.counter + 0x1000_u16.wrapping_mul(i as u16)).index();
-            ((self.buffer[sample_index] as i32).saturating_mul(coefficients[i as usize])) >> 11
+            ((self.buffer[sample_index] as i32) * coefficients[i]) >> 11
         });
-        let sample = samples.fold(0i32, |acc, x| acc.saturating_add(x));
-        let sample = sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        // The hardware sums the first three taps with 16 bit wrap-around, then adds the last tap
+        // with clamping and drops the lowest bit.
+        let partial = samples[0..3]
+            .iter()
+            .fold(0i16, |acc, x| acc.wrapping_add(*x as i16));
+        let sample = (partial as i32 + samples[3]).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        let sample = sample & !1;
 
         let (new_counter, cross) = self.counter.add_detect_4byte_cross(pitch);
         if cross {
@@ -202,7 +207,7 @@ mod test {
         test_interpolation(
             0x1000,
             RECT.to_vec(),
-            vec![0, 0, 18, 81, 99, 99, 81, 18, 0, 0, 0, 0],
+            vec![0, 0, 18, 80, 98, 98, 80, 18, 0, 0, 0, 0],
         )
     }
 
@@ -212,14 +217,30 @@ mod test {
             0x0800,
             RECT.to_vec(),
             vec![
-                0, 0, 0, 2, 18, 49, 81, 96, 99, 98, 99, 96, 81, 49, 18, 2, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 2, 18, 48, 80, 96, 98, 98, 98, 96, 80, 48, 18, 2, 0, 0, 0, 0, 0, 0, 0, 0,
             ],
         )
     }
 
     #[test]
     pub fn test_double_pitch() {
-        test_interpolation(0x2000, RECT.to_vec(), vec![0, 18, 99, 81, 0, 0])
+        test_interpolation(0x2000, RECT.to_vec(), vec![0, 18, 98, 80, 0, 0])
+    }
+
+    #[test]
+    pub fn test_overflow() {
+        // At fraction 0 the sum of the first three taps wraps around to a negative value, at
+        // other fractions the sum of all taps is clamped.
+        test_interpolation(
+            0x0400,
+            vec![i16::MAX; 12],
+            vec![-32756, 32764, 32764, 32764, -32756],
+        );
+        test_interpolation(
+            0x0400,
+            vec![i16::MIN; 12],
+            vec![32752, -32768, -32768, -32768, 32752],
+        );
     }
 
     fn test_interpolation(pitch: u16, input_vec: Vec<i16>, expected: Vec<i16>) {
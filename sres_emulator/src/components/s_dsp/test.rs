@@ -8,3 +8,305 @@ fn test_read_write_register() {
     s_dsp.write_register(0x0, 0x12);
     assert_eq!(s_dsp.read_register(0x0), 0x12);
 }
+
+/// Memory with a looping block of constant positive samples as source 0 and a two block
+/// sample ending without loop as source 1. The sample directory is at 0x100.
+fn test_memory() -> Vec<u8> {
+    let mut memory = vec![0; 0x10000];
+    memory[0x100..0x108].copy_from_slice(&[0x00, 0x02, 0x00, 0x02, 0x00, 0x03, 0x00, 0x03]);
+    // Range 12, loop and end flags
+    memory[0x200] = 0xC3;
+    memory[0x201..0x209].fill(0x77);
+    // Silent block followed by a silent end block
+    memory[0x300] = 0x00;
+    memory[0x309] = 0x01;
+    memory
+}
+
+fn setup_voice(s_dsp: &mut SDsp, voice: u8, source: u8, pitch: u16) {
+    let base = voice << 4;
+    s_dsp.write_register(base | 0x2, pitch as u8);
+    s_dsp.write_register(base | 0x3, (pitch >> 8) as u8);
+    s_dsp.write_register(base | 0x4, source);
+    s_dsp.write_register(base | 0x5, 0x00); // ADSR disabled
+    s_dsp.write_register(base | 0x7, 0x7E); // Fixed gain
+}
+
+/// Like `setup_voice`, but also sets the voice volume so it is audible in the output.
+fn setup_audible_voice(s_dsp: &mut SDsp, voice: u8, source: u8, pitch: u16) {
+    setup_voice(s_dsp, voice, source, pitch);
+    s_dsp.write_register(voice << 4, 0x40);
+    s_dsp.write_register(voice << 4 | 0x1, 0x40);
+}
+
+/// Returns the number of samples until voice 1 reaches the end of its sample.
+fn samples_until_end(pitch_modulation: bool) -> usize {
+    let memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.write_register(0x5D, 0x01);
+    setup_voice(&mut s_dsp, 0, 0, 0x1000);
+    setup_voice(&mut s_dsp, 1, 1, 0x0400);
+    s_dsp.write_register(0x2D, if pitch_modulation { 0x02 } else { 0x00 });
+    s_dsp.write_register(0x4C, 0x03);
+    (1..1000)
+        .find(|_| {
+            s_dsp.generate_sample(&memory);
+            s_dsp.read_register(0x7C).bit(1)
+        })
+        .unwrap()
+}
+
+#[test]
+fn test_pitch_modulation() {
+    // Voice 0 outputs a positive signal, which speeds up voice 1.
+    let unmodulated = samples_until_end(false);
+    let modulated = samples_until_end(true);
+    assert!(modulated < unmodulated, "{modulated} >= {unmodulated}");
+}
+
+#[test]
+fn test_endx() {
+    let memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.write_register(0x5D, 0x01);
+    setup_voice(&mut s_dsp, 1, 1, 0x1000);
+    s_dsp.write_register(0x4C, 0x02);
+    s_dsp.generate_sample(&memory);
+    assert_eq!(s_dsp.read_register(0x7C), 0x00);
+
+    for _ in 0..32 {
+        s_dsp.generate_sample(&memory);
+    }
+    assert_eq!(s_dsp.read_register(0x7C), 0x02);
+
+    // Any write clears all flags
+    s_dsp.write_register(0x7C, 0xFF);
+    assert_eq!(s_dsp.read_register(0x7C), 0x00);
+
+    // Key on clears the flag as well
+    for _ in 0..32 {
+        s_dsp.generate_sample(&memory);
+    }
+    s_dsp.write_register(0x4C, 0x02);
+    s_dsp.generate_sample(&memory);
+    assert_eq!(s_dsp.read_register(0x7C), 0x00);
+}
+
+#[test]
+fn test_end_without_loop_silences_voice() {
+    let memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.write_register(0x5D, 0x01);
+    setup_voice(&mut s_dsp, 0, 0, 0x1000);
+    setup_voice(&mut s_dsp, 1, 1, 0x1000);
+    s_dsp.write_register(0x4C, 0x03);
+    s_dsp.generate_sample(&memory);
+    assert_eq!(s_dsp.read_register(0x18), 0x7E);
+
+    for _ in 0..32 {
+        s_dsp.generate_sample(&memory);
+    }
+    // The looping voice keeps playing, the other one is released with its envelope at 0
+    assert_eq!(s_dsp.read_register(0x08), 0x7E);
+    assert_eq!(s_dsp.read_register(0x18), 0x00);
+    let envelope = &s_dsp.voices[1].envelope;
+    assert_eq!(envelope.state(), voice::EnvelopeState::Release);
+}
+
+#[test]
+fn test_key_off() {
+    let memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.write_register(0x5D, 0x01);
+    setup_voice(&mut s_dsp, 0, 0, 0x1000);
+    s_dsp.write_register(0x4C, 0x01);
+    s_dsp.generate_sample(&memory);
+    assert_eq!(s_dsp.read_register(0x08), 0x7E);
+
+    // Release decreases the envelope by 8 every sample
+    s_dsp.write_register(0x5C, 0x01);
+    s_dsp.generate_sample(&memory);
+    let envelope = &s_dsp.voices[0].envelope;
+    assert_eq!(envelope.state(), voice::EnvelopeState::Release);
+    assert_eq!(envelope.value(), 0x7E0 - 8);
+
+    // Key on has no effect while the key off bit is set
+    s_dsp.write_register(0x4C, 0x01);
+    s_dsp.generate_sample(&memory);
+    let envelope = &s_dsp.voices[0].envelope;
+    assert_eq!(envelope.state(), voice::EnvelopeState::Release);
+}
+
+/// Steps the DSP until `reg` is not zero and returns the step within the sample period at which
+/// it changed.
+fn step_until_set(s_dsp: &mut SDsp, memory: &[u8], reg: u8) -> u8 {
+    for _ in 0..(32 * 64) {
+        let step = s_dsp.cycle_state.step;
+        s_dsp.step_cycle(memory);
+        if s_dsp.read_register(reg) != 0 {
+            return step;
+        }
+    }
+    panic!("Register {reg:02X} was not set");
+}
+
+fn cycle_stepped_dsp(source: u8) -> SDsp {
+    let mut s_dsp = SDsp::default();
+    s_dsp.set_timing(DspTiming::Cycle);
+    s_dsp.write_register(0x5D, 0x01);
+    setup_audible_voice(&mut s_dsp, 1, source, 0x1000);
+    s_dsp.write_register(0x4C, 0x02);
+    s_dsp
+}
+
+#[test]
+fn test_cycle_register_timing() {
+    let memory = test_memory();
+    // Voice 1 is processed in step 1, its OUTX and ENVX registers are written in step 6 and 7.
+    let mut s_dsp = cycle_stepped_dsp(0);
+    assert_eq!(step_until_set(&mut s_dsp, &memory, 0x19), 6);
+    let mut s_dsp = cycle_stepped_dsp(0);
+    assert_eq!(step_until_set(&mut s_dsp, &memory, 0x18), 7);
+    // ENDX is written in the V7 step of each voice, which is step 5 for voice 1.
+    let mut s_dsp = cycle_stepped_dsp(1);
+    assert_eq!(step_until_set(&mut s_dsp, &memory, 0x7C), 5);
+}
+
+#[test]
+fn test_cycle_timing_matches_sample_timing() {
+    let memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.write_register(0x5D, 0x01);
+    setup_audible_voice(&mut s_dsp, 1, 0, 0x0C00);
+    s_dsp.write_register(0x4C, 0x02);
+    let expected: Vec<i16> = (0..64).map(|_| s_dsp.generate_sample(&memory)).collect();
+
+    let mut s_dsp = cycle_stepped_dsp(0);
+    s_dsp.write_register(0x12, 0x00);
+    s_dsp.write_register(0x13, 0x0C);
+    let actual: Vec<i16> = (0..(32 * 66))
+        .filter_map(|_| s_dsp.step_cycle(&memory))
+        .collect();
+
+    // The cycle-stepped DSP generates the same samples, but delayed until KON is latched.
+    let delay = actual.iter().position(|sample| *sample != 0).unwrap();
+    assert!(delay <= 2, "Delayed by {delay} samples");
+    assert_eq!(&actual[delay..delay + 64], &expected[..]);
+}
+
+#[test]
+fn test_voice_overrides_and_capture() {
+    let memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.write_register(0x5D, 0x01);
+    setup_audible_voice(&mut s_dsp, 0, 0, 0x1000);
+    setup_audible_voice(&mut s_dsp, 2, 0, 0x1000);
+    s_dsp.write_register(0x20, 0x20);
+    s_dsp.write_register(0x4C, 0x05);
+    for _ in 0..16 {
+        s_dsp.generate_sample(&memory);
+    }
+
+    s_dsp.start_voice_capture();
+    let generate = |s_dsp: &mut SDsp, overrides: VoiceOverrides| {
+        s_dsp.set_voice_overrides(overrides);
+        s_dsp.generate_sample(&memory)
+    };
+    let all = generate(&mut s_dsp, VoiceOverrides::default());
+    let mute_voice_0 = VoiceOverrides {
+        mute: 0x01,
+        ..Default::default()
+    };
+    let muted = generate(&mut s_dsp, mute_voice_0);
+    let solo_voice_0 = VoiceOverrides {
+        solo: 0x01,
+        ..Default::default()
+    };
+    let solo = generate(&mut s_dsp, solo_voice_0);
+    let capture = s_dsp.stop_voice_capture().unwrap();
+    assert!(!s_dsp.is_capturing_voices());
+
+    let voice_0 = capture.samples(0);
+    let voice_2 = capture.samples(2);
+    assert_eq!(voice_0.len(), 3);
+    assert_ne!(voice_0[0], voice_2[0]);
+    assert_eq!(all, voice_0[0] + voice_2[0]);
+    assert_eq!(muted, voice_2[1]);
+    assert_eq!(solo, voice_0[2]);
+    assert_eq!(capture.samples(1), &[0, 0, 0]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("voice0.wav");
+    capture.save_wav(0, &path).unwrap();
+    let saved: Vec<i16> = hound::WavReader::open(&path)
+        .unwrap()
+        .samples()
+        .map(|s| s.unwrap())
+        .collect();
+    assert_eq!(saved, voice_0);
+}
+
+#[test]
+fn test_voice_capture_limit() {
+    let memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.start_voice_capture();
+    if let Some(capture) = &mut s_dsp.voice_capture {
+        capture.voices[0] = vec![0; MAX_VOICE_CAPTURE_SAMPLES - 1];
+    }
+    s_dsp.generate_sample(&memory);
+    s_dsp.generate_sample(&memory);
+    let capture = s_dsp.stop_voice_capture().unwrap();
+    assert_eq!(capture.samples(0).len(), MAX_VOICE_CAPTURE_SAMPLES);
+    assert_eq!(capture.samples(1).len(), 2);
+}
+
+#[test]
+fn test_samples() {
+    let mut memory = test_memory();
+    let mut s_dsp = SDsp::default();
+    s_dsp.write_register(0x5D, 0x01);
+    setup_voice(&mut s_dsp, 0, 1, 0x1000);
+    setup_voice(&mut s_dsp, 1, 0, 0x1000);
+    setup_voice(&mut s_dsp, 2, 1, 0x1000);
+    // Only the sources selected by a voice are listed, each of them once.
+    let samples = s_dsp.debug().samples(&memory);
+    let sources: Vec<u8> = samples.iter().map(|sample| sample.source).collect();
+    assert_eq!(sources, [0, 1]);
+
+    let looping = &samples[0];
+    assert_eq!((looping.start_addr, looping.loop_addr), (0x200, 0x200));
+    assert_eq!(looping.samples.len(), 16);
+    assert_eq!(looping.loop_start, Some(0));
+    assert_eq!(looping.playback(40).len(), 48);
+
+    let one_shot = &samples[1];
+    assert_eq!(one_shot.samples.len(), 32);
+    assert_eq!(one_shot.loop_start, None);
+    assert_eq!(one_shot.playback(40).len(), 32);
+
+    // A loop into the second block of a sample.
+    memory[0x104..0x108].copy_from_slice(&[0x00, 0x03, 0x09, 0x03]);
+    memory[0x309] = 0x03;
+    let sample = BrrSample::decode(&memory, 1, 0x300, 0x309).unwrap();
+    assert_eq!(sample.loop_start, Some(16));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sample.wav");
+    sample.save_wav(&path).unwrap();
+    let saved: Vec<i16> = hound::WavReader::open(&path)
+        .unwrap()
+        .samples()
+        .map(|s| s.unwrap())
+        .collect();
+    assert_eq!(saved, sample.samples);
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(
+        &data[data.len() - 68..data.len() - 60],
+        b"smpl\x3C\x00\x00\x00"
+    );
+    let loop_points = &data[data.len() - 16..data.len() - 8];
+    assert_eq!(loop_points, &[16, 0, 0, 0, 31, 0, 0, 0]);
+    let riff_size = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    assert_eq!(riff_size as usize, data.len() - 8);
+}
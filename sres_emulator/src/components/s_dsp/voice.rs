@@ -72,6 +72,12 @@ impl DspEnvelope {
         self.state = EnvelopeState::Release;
     }
 
+    /// Switch to the release phase and silence the voice immediately
+    pub fn silence(&mut self) {
+        self.value = 0;
+        self.state = EnvelopeState::Release;
+    }
+
     /// Update envelope for one sample period
     pub fn update(&mut self, global_counter: u16, adsr1: Adsr1, adsr2: Adsr2, gain: Gain) {
         if adsr1.enable() {
@@ -252,6 +258,10 @@ pub struct Voice {
     pub envx_buffer: AudioRingBuffer<OUTX_BUFFER_SIZE>,
     pub outx_buffer: AudioRingBuffer<OUTX_BUFFER_SIZE>,
 
+    /// Output of the last sample after applying the envelope, used for pitch modulation of
+    /// the next voice.
+    pub output: i16,
+
     brr_decoder: BrrDecoder,
     pitch_generator: PitchGenerator,
 }
@@ -314,12 +324,16 @@ impl Voice {
     }
 
     pub fn dir_info(&self, memory: &[u8], dir: usize) -> (u16, u16) {
-        let source_addr = dir + self.sample_source as usize * 4;
-        let start_addr = u16::from_le_bytes([memory[source_addr], memory[source_addr + 1]]);
-        let loop_addr = u16::from_le_bytes([memory[source_addr + 2], memory[source_addr + 3]]);
-        (start_addr, loop_addr)
+        dir_entry(memory, dir, self.sample_source)
+    }
+
+    /// Returns true if the BRR decoder reached a block with the end flag since the last call.
+    pub fn take_brr_end(&mut self) -> bool {
+        self.brr_decoder.take_end_reached()
     }
 
+    /// Generates the next sample. If `modulation` is provided, the pitch is modulated by this
+    /// output of the previous voice (PMON).
     pub fn generate_sample_with_noise(
         &mut self,
         memory: &[u8],
@@ -327,6 +341,41 @@ impl Voice {
         use_noise: bool,
         noise_bits: u16,
         global_counter: u16,
+        modulation: Option<i16>,
+    ) -> i16 {
+        let sample = self.process_sample(
+            memory,
+            dir,
+            use_noise,
+            noise_bits,
+            global_counter,
+            modulation,
+        );
+        self.latch_envx();
+        self.latch_outx();
+        sample
+    }
+
+    /// Updates the ENVX register with the current envelope.
+    pub fn latch_envx(&mut self) {
+        self.envx = self.envelope.envx();
+    }
+
+    /// Updates the OUTX register with the output of the last sample.
+    pub fn latch_outx(&mut self) {
+        self.outx = (self.output >> 8) as i8;
+    }
+
+    /// Same as [Voice::generate_sample_with_noise], but leaves the ENVX and OUTX registers
+    /// unchanged. The cycle-stepped DSP updates them later in the sample period.
+    pub fn process_sample(
+        &mut self,
+        memory: &[u8],
+        dir: usize,
+        use_noise: bool,
+        noise_bits: u16,
+        global_counter: u16,
+        modulation: Option<i16>,
     ) -> i16 {
         if self.trigger_on {
             let (start_addr, loop_addr) = self.dir_info(memory, dir);
@@ -346,7 +395,6 @@ impl Voice {
         // Update envelope
         self.envelope
             .update(global_counter, self.adsr1, self.adsr2, self.gain);
-        self.envx = self.envelope.envx();
 
         let sample = if use_noise {
             // Use bit 0 of noise_bits as the noise sample
@@ -356,15 +404,25 @@ impl Voice {
                 0x4000
             }
         } else {
+            let pitch = match modulation {
+                Some(output) => modulated_pitch(self.pitch, output),
+                None => self.pitch,
+            };
             self.pitch_generator
-                .generate_sample(self.pitch, &mut self.brr_decoder.iter(memory))
+                .generate_sample(pitch, &mut self.brr_decoder.iter(memory))
         };
 
         // Apply envelope to sample
         let enveloped_sample = ((sample as i32) * (self.envelope.value() as i32)) >> 11;
-        self.outx = (enveloped_sample >> 8) as i8;
+        self.output = enveloped_sample as i16;
         self.outx_buffer.push(sample);
 
+        // Reaching a block that ends without looping silences the voice right away, so the last
+        // block of a sample that does not loop is never heard.
+        if self.brr_decoder.end_without_loop() {
+            self.envelope.silence();
+        }
+
         // Apply volume
         let left = (enveloped_sample * (self.vol_l as i32)) >> 7;
         let right = (enveloped_sample * (self.vol_r as i32)) >> 7;
@@ -372,6 +430,24 @@ impl Voice {
     }
 }
 
+/// Returns the start and loop address of `source` in the sample directory at `dir`.
+pub fn dir_entry(memory: &[u8], dir: usize, source: u8) -> (u16, u16) {
+    let source_addr = dir + source as usize * 4;
+    // Addresses wrap around at the end of memory.
+    let byte = |offset: usize| memory[(source_addr + offset) & 0xFFFF];
+    let start_addr = u16::from_le_bytes([byte(0), byte(1)]);
+    let loop_addr = u16::from_le_bytes([byte(2), byte(3)]);
+    (start_addr, loop_addr)
+}
+
+/// Pitch modulated by the output of the previous voice: P + ((output >> 5) * P >> 10).
+///
+/// The result is limited to the 14 bit range of the pitch register.
+pub fn modulated_pitch(pitch: u16, output: i16) -> u16 {
+    let pitch = pitch as i32;
+    (pitch + (((output as i32 >> 5) * pitch) >> 10)).clamp(0, 0x3FFF) as u16
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct AudioRingBuffer<const N: usize> {
     samples: [i16; N],
@@ -489,6 +565,7 @@ mod test {
             trigger_on: true,
             trigger_off: false,
             envelope: DspEnvelope::new(),
+            output: 0,
             outx_buffer: AudioRingBuffer::default(),
             envx_buffer: AudioRingBuffer::default(),
             brr_decoder: BrrDecoder::default(),
@@ -497,8 +574,18 @@ mod test {
 
         const NUM_SAMPLES: usize = 7936; // Length of the play_brr_sample sample
         let output: Vec<i16> = (0..NUM_SAMPLES)
-            .map(|i| voice.generate_sample_with_noise(&memory, 0x0300, false, 0, i as u16))
+            .map(|i| voice.generate_sample_with_noise(&memory, 0x0300, false, 0, i as u16, None))
             .collect();
         compare_wav_against_golden(&output, &prefix)
     }
+
+    #[test]
+    fn test_modulated_pitch() {
+        assert_eq!(modulated_pitch(0x1000, 0), 0x1000);
+        // Full positive output almost doubles the pitch, full negative output stops it.
+        assert_eq!(modulated_pitch(0x1000, i16::MAX), 0x1FFC);
+        assert_eq!(modulated_pitch(0x1000, i16::MIN), 0);
+        assert_eq!(modulated_pitch(0x1000, 0x2000), 0x1400);
+        assert_eq!(modulated_pitch(0x3000, i16::MAX), 0x3FFF);
+    }
 }
@@ -28,6 +28,17 @@ pub trait Spc700Bus: Bus<AddressU16> {
     fn update_master_clock(&mut self, cycles: u64);
 }
 
+/// Registers of the SPC700, e.g. to store or restore them from .spc files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Spc700Registers {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub psw: u8,
+    pub sp: u8,
+}
+
 pub struct Spc700<BusT: Spc700Bus> {
     pub bus: BusT,
     debug_event_collector: DebugEventCollectorRef<Spc700Event>,
@@ -61,6 +72,26 @@ impl<BusT: Spc700Bus> Spc700<BusT> {
         Spc700Debug(self)
     }
 
+    pub fn registers(&self) -> Spc700Registers {
+        Spc700Registers {
+            pc: self.pc.0,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            psw: self.status.into(),
+            sp: self.sp,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: Spc700Registers) {
+        self.pc = AddressU16(registers.pc);
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.status = registers.psw.into();
+        self.sp = registers.sp;
+    }
+
     pub fn reset(&mut self) {
         self.pc = AddressU16(0xFFC0);
         self.sp = 0xef;
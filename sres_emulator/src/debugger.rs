@@ -15,6 +15,7 @@ use num_traits::PrimInt;
 
 use crate::apu::ApuBusEvent;
 use crate::common::address::AddressU24;
+use crate::common::clock::ClockInfo;
 use crate::common::debug_events::DebugErrorCollector;
 use crate::common::debug_events::DebugEventCollector;
 use crate::common::debug_events::DEBUG_EVENTS_ENABLED;
@@ -25,6 +26,7 @@ use crate::components::cpu::NativeVectorTable;
 use crate::components::spc700::Spc700Event;
 use crate::components::spc700::Spc700State;
 use crate::main_bus::MainBusEvent;
+use crate::main_bus::TimedEvent;
 
 #[derive(Clone, Debug, PartialEq, strum::Display)]
 pub enum DebugEvent {
@@ -169,6 +171,54 @@ pub enum Trigger {
     Interrupt(NativeVectorTable),
 }
 
+/// Records the timed events of the current and the previous frame for the event viewer.
+#[derive(Default)]
+pub struct FrameEvents {
+    pub enabled: bool,
+    frame: u64,
+    current: Vec<TimedEvent>,
+    previous: Vec<TimedEvent>,
+}
+
+impl FrameEvents {
+    pub fn record(&mut self, event: TimedEvent) {
+        if !self.enabled {
+            return;
+        }
+        if event.clock.f != self.frame {
+            if event.clock.f == self.frame + 1 {
+                self.previous = std::mem::take(&mut self.current);
+            } else {
+                self.previous.clear();
+                self.current.clear();
+            }
+            self.frame = event.clock.f;
+        }
+        self.current.push(event);
+    }
+
+    /// Returns the events of one full frame ending at `now`: Everything that happened in the
+    /// current frame so far, followed by the events of the previous frame after `now`.
+    pub fn events(&self, now: ClockInfo) -> Vec<&TimedEvent> {
+        let (current, previous): (&[TimedEvent], &[TimedEvent]) = if now.f == self.frame {
+            (&self.current, &self.previous)
+        } else if now.f == self.frame + 1 {
+            (&[], &self.current)
+        } else {
+            (&[], &[])
+        };
+        let now_position = (now.v, now.h_counter);
+        current
+            .iter()
+            .chain(
+                previous
+                    .iter()
+                    .filter(|event| (event.clock.v, event.clock.h_counter) > now_position),
+            )
+            .collect()
+    }
+}
+
 pub type DebuggerRef = Arc<Mutex<Debugger>>;
 
 pub struct Debugger {
@@ -176,6 +226,7 @@ pub struct Debugger {
     pub break_points: Vec<EventFilter>,
     pub log: RingBuffer<DebugEvent, 1024>,
     pub break_reason: Option<BreakReason>,
+    pub frame_events: FrameEvents,
     pub enabled: bool,
 }
 
@@ -186,6 +237,7 @@ impl Debugger {
             break_points: Vec::new(),
             log: RingBuffer::default(),
             break_reason: None,
+            frame_events: FrameEvents::default(),
             enabled: false,
         }))
     }
@@ -345,6 +397,13 @@ impl DebugEventCollector<MainBusEvent> for Debugger {
     }
 }
 
+impl DebugEventCollector<TimedEvent> for Debugger {
+    #[cold]
+    fn on_event(&mut self, event: TimedEvent) {
+        self.frame_events.record(event);
+    }
+}
+
 impl DebugEventCollector<()> for Debugger {
     #[cold]
     fn on_event(&mut self, _event: ()) {}
@@ -416,6 +475,59 @@ mod test {
         assert_eq!(format_range(&(0..u32::MAX)), "");
     }
 
+    #[test]
+    fn test_frame_events() {
+        use crate::main_bus::TimedEventKind;
+
+        let event = |f: u64, v: u64, kind: TimedEventKind| TimedEvent {
+            clock: ClockInfo {
+                f,
+                v,
+                ..ClockInfo::default()
+            },
+            kind,
+        };
+        let kinds = |events: Vec<&TimedEvent>| -> Vec<TimedEventKind> {
+            events.iter().map(|event| event.kind).collect()
+        };
+        let now = |f: u64, v: u64| ClockInfo {
+            f,
+            v,
+            ..ClockInfo::default()
+        };
+
+        let mut frame_events = FrameEvents::default();
+        frame_events.record(event(0, 10, TimedEventKind::Irq));
+        assert!(frame_events.events(now(0, 20)).is_empty());
+
+        frame_events.enabled = true;
+        frame_events.record(event(0, 10, TimedEventKind::Irq));
+        frame_events.record(event(0, 225, TimedEventKind::Nmi));
+        assert_eq!(
+            kinds(frame_events.events(now(0, 230))),
+            vec![TimedEventKind::Irq, TimedEventKind::Nmi]
+        );
+
+        // Events of the previous frame are shown until the current frame reaches them.
+        assert_eq!(
+            kinds(frame_events.events(now(1, 20))),
+            vec![TimedEventKind::Nmi]
+        );
+        frame_events.record(event(1, 50, TimedEventKind::Dma(16)));
+        assert_eq!(
+            kinds(frame_events.events(now(1, 60))),
+            vec![TimedEventKind::Dma(16), TimedEventKind::Nmi]
+        );
+
+        // Skipping frames drops stale events.
+        frame_events.record(event(3, 5, TimedEventKind::Irq));
+        assert_eq!(
+            kinds(frame_events.events(now(3, 10))),
+            vec![TimedEventKind::Irq]
+        );
+        assert!(frame_events.events(now(5, 0)).is_empty());
+    }
+
     #[test]
     fn test_trace_filter_format() {
         let check_format = |filter: &str, expected: EventFilter| {
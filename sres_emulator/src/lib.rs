@@ -4,6 +4,7 @@ pub mod components;
 pub mod controller;
 pub mod debugger;
 pub mod main_bus;
+pub mod recording;
 
 use std::ops::Deref;
 use std::sync::MutexGuard;
@@ -11,6 +12,7 @@ use std::sync::MutexGuard;
 use common::util::EdgeDetector;
 use components::ppu::Framebuffer;
 use components::ppu::PpuDebug;
+use log::error;
 
 use crate::apu::Apu;
 use crate::apu::ApuDebug;
@@ -21,6 +23,11 @@ use crate::components::cartridge::Cartridge;
 use crate::components::cpu::Cpu;
 use crate::components::cpu::MainBus;
 use crate::components::ppu::Ppu;
+use crate::components::ppu::PpuDebugOverrides;
+use crate::components::ppu::RenderMode;
+use crate::components::s_dsp::DspTiming;
+use crate::components::s_dsp::VoiceCapture;
+use crate::components::s_dsp::VoiceOverrides;
 use crate::debugger::BreakReason;
 use crate::debugger::Debugger;
 use crate::debugger::DebuggerRef;
@@ -30,6 +37,7 @@ use crate::main_bus::devices::BatchedBusDeviceU24;
 use crate::main_bus::devices::ManagedBusDeviceU24;
 use crate::main_bus::devices::SyncBusDevice;
 use crate::main_bus::MainBusImpl;
+use crate::recording::FrameSink;
 
 pub enum ExecutionResult {
     Normal,
@@ -58,6 +66,7 @@ pub struct SystemImpl<PpuT: ManagedBusDeviceU24<Ppu>, ApuT: ManagedBusDeviceU24<
     vblank_detector: EdgeDetector,
     has_pending_video_frame: bool,
     pending_video_frame: Framebuffer,
+    frame_sink: Option<Box<dyn FrameSink>>,
 }
 
 impl<PpuT: ManagedBusDeviceU24<Ppu>, ApuT: ManagedBusDeviceU24<Apu>> SystemImpl<PpuT, ApuT> {
@@ -69,6 +78,7 @@ impl<PpuT: ManagedBusDeviceU24<Ppu>, ApuT: ManagedBusDeviceU24<Apu>> SystemImpl<
             vblank_detector: EdgeDetector::new(),
             has_pending_video_frame: false,
             pending_video_frame: Framebuffer::default(),
+            frame_sink: None,
         };
         system.cpu.reset();
         system
@@ -87,7 +97,7 @@ impl BatchedSystem {
             Cpu::new(
                 MainBusImpl::new(
                     cartridge,
-                    BatchedBusDeviceU24::new(Ppu::new()),
+                    BatchedBusDeviceU24::new(Ppu::new(DebugEventCollectorRef(debugger.clone()))),
                     BatchedBusDeviceU24::new(Apu::new(debugger.clone())),
                     debugger.clone(),
                 ),
@@ -110,7 +120,7 @@ impl SyncSystem {
             Cpu::new(
                 MainBusImpl::new(
                     cartridge,
-                    SyncBusDevice::new(Ppu::new()),
+                    SyncBusDevice::new(Ppu::new(DebugEventCollectorRef(debugger.clone()))),
                     SyncBusDevice::new(Apu::new(debugger.clone())),
                     debugger.clone(),
                 ),
@@ -133,7 +143,7 @@ impl AsyncSystem {
             Cpu::new(
                 MainBusImpl::new(
                     cartridge,
-                    AsyncBusDeviceU24::new(Ppu::new()),
+                    AsyncBusDeviceU24::new(Ppu::new(DebugEventCollectorRef(debugger.clone()))),
                     AsyncBusDeviceU24::new(Apu::new(debugger.clone())),
                     debugger.clone(),
                 ),
@@ -214,6 +224,7 @@ impl<PpuT: ManagedBusDeviceU24<Ppu>, ApuT: ManagedBusDeviceU24<Apu>> SystemImpl<
         if self.has_pending_video_frame {
             std::mem::swap(&mut self.pending_video_frame, buffer);
             self.has_pending_video_frame = false;
+            self.write_to_frame_sink(|sink| sink.video_frame(buffer));
             true
         } else {
             false
@@ -221,13 +232,81 @@ impl<PpuT: ManagedBusDeviceU24<Ppu>, ApuT: ManagedBusDeviceU24<Apu>> SystemImpl<
     }
 
     pub fn swap_audio_buffer(&mut self, buffer: &mut AudioBuffer) {
-        self.cpu.bus.apu.inner_mut().swap_audio_buffer(buffer)
+        self.cpu.bus.apu.inner_mut().swap_audio_buffer(buffer);
+        self.write_to_frame_sink(|sink| sink.audio_samples(buffer));
+    }
+
+    /// Installs a sink that receives every frame returned by [Self::swap_video_frame] and all
+    /// samples returned by [Self::swap_audio_buffer].
+    pub fn set_frame_sink(&mut self, sink: Box<dyn FrameSink>) {
+        self.frame_sink = Some(sink);
+    }
+
+    /// Removes the frame sink and flushes its output.
+    pub fn remove_frame_sink(&mut self) -> anyhow::Result<()> {
+        match self.frame_sink.take() {
+            Some(mut sink) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn has_frame_sink(&self) -> bool {
+        self.frame_sink.is_some()
+    }
+
+    /// Forwards output to the frame sink. The sink is finished and dropped if it fails.
+    fn write_to_frame_sink(
+        &mut self,
+        write: impl FnOnce(&mut dyn FrameSink) -> anyhow::Result<()>,
+    ) {
+        if let Some(sink) = self.frame_sink.as_mut() {
+            if let Err(err) = write(sink.as_mut()) {
+                error!("Frame sink failed, stopping recording: {err}");
+                if let Err(err) = self.remove_frame_sink() {
+                    error!("Failed to finish frame sink: {err}");
+                }
+            }
+        }
     }
 
     pub fn force_headless(&mut self) {
         self.cpu.bus.ppu.inner_mut().force_headless();
     }
 
+    pub fn ppu_debug_overrides(&self) -> PpuDebugOverrides {
+        self.cpu.bus.ppu.inner().debug_overrides()
+    }
+
+    /// Overrides the game's PPU settings, e.g. to hide layers while debugging.
+    pub fn set_ppu_debug_overrides(&mut self, overrides: PpuDebugOverrides) {
+        self.cpu.bus.ppu.inner_mut().set_debug_overrides(overrides);
+    }
+
+    /// Selects whether the DSP generates whole samples at once or is stepped with each SPC700
+    /// cycle.
+    pub fn set_dsp_timing(&mut self, timing: DspTiming) {
+        self.cpu.bus.apu.inner_mut().set_dsp_timing(timing);
+    }
+
+    /// Mutes or solos individual DSP voices.
+    pub fn set_dsp_voice_overrides(&mut self, overrides: VoiceOverrides) {
+        self.cpu.bus.apu.inner_mut().set_voice_overrides(overrides);
+    }
+
+    /// Starts recording the output of each DSP voice, see [Self::stop_voice_capture].
+    pub fn start_voice_capture(&mut self) {
+        self.cpu.bus.apu.inner_mut().start_voice_capture();
+    }
+
+    pub fn stop_voice_capture(&mut self) -> Option<VoiceCapture> {
+        self.cpu.bus.apu.inner_mut().stop_voice_capture()
+    }
+
+    /// Selects whether PPU register writes can take effect in the middle of a scanline.
+    pub fn set_ppu_render_mode(&mut self, render_mode: RenderMode) {
+        self.cpu.bus.ppu.inner_mut().set_render_mode(render_mode);
+    }
+
     pub fn save_ppu_state(&self) -> Vec<u8> {
         self.cpu.bus.ppu.inner().save_state()
     }
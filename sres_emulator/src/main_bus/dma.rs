@@ -14,6 +14,9 @@ use crate::common::debug_events::DebugEventCollectorRef;
 use crate::common::uint::U16Ext;
 use crate::common::uint::U8Ext;
 
+/// A single byte transferred by DMA: (cycle offset from the start of the DMA, source, destination)
+pub type DmaTransfer = (u64, AddressU24, AddressU24);
+
 pub struct DmaController {
     dma_channels: [DmaChannel; 8],
     dma_pending: u8,
@@ -39,11 +42,12 @@ impl DmaController {
         }
     }
 
+    /// Returns the transfers of a pending DMA and its total duration in master cycles.
     pub fn pending_transfers(
         &mut self,
         master_clock: u64,
         clock_speed: u64,
-    ) -> Option<(Vec<(AddressU24, AddressU24)>, u64)> {
+    ) -> Option<(Vec<DmaTransfer>, u64)> {
         if !self.dma_active {
             return None;
         }
@@ -53,7 +57,7 @@ impl DmaController {
 
         let mut transfer_duration = 0;
         let mut channel_overhead: u64 = 0;
-        let mut transfers: Vec<(AddressU24, AddressU24)> = Vec::new();
+        let mut transfers: Vec<DmaTransfer> = Vec::new();
         for channel_idx in 0..8_usize {
             if self.dma_pending.bit(channel_idx) {
                 let channel = &mut self.dma_channels[channel_idx];
@@ -74,15 +78,19 @@ impl DmaController {
                     DmaTransferPattern::Undocumented_0_0_1_1 => vec![0, 0, 1, 1],
                 };
 
+                channel_overhead += 8;
                 for idx in 0..length {
+                    let offset =
+                        start_sync_overhead + dma_overhead + channel_overhead + transfer_duration;
+                    transfer_duration += 8;
                     let bus_b_address = channel
                         .bus_b_address
                         .add(bus_b_pattern[idx % bus_b_pattern.len()], Wrap::NoWrap);
 
                     if channel.parameters.direction {
-                        transfers.push((bus_b_address, channel.bus_a_address));
+                        transfers.push((offset, bus_b_address, channel.bus_a_address));
                     } else {
-                        transfers.push((channel.bus_a_address, bus_b_address));
+                        transfers.push((offset, channel.bus_a_address, bus_b_address));
                     }
 
                     let increment = if channel.parameters.fixed {
@@ -95,9 +103,6 @@ impl DmaController {
                     channel.bus_a_address =
                         channel.bus_a_address.add_signed(increment, Wrap::NoWrap);
                 }
-
-                transfer_duration += 8 * length as u64;
-                channel_overhead += 8;
             }
         }
 
@@ -381,3 +386,34 @@ pub struct DmaParameters {
     #[packed_field(size_bits = "3", ty = "enum")]
     pub transfer_pattern: DmaTransferPattern,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::debug_events::test::mock_collector;
+
+    #[test]
+    fn test_transfer_timing() {
+        let mut dma = DmaController::new(mock_collector());
+        // Channel 0 transfers 3 bytes to VMDATA, channel 1 transfers 2 bytes to CGDATA
+        for (addr, value) in [
+            (0x4300, 0x01),
+            (0x4301, 0x18),
+            (0x4305, 0x03),
+            (0x4311, 0x22),
+            (0x4315, 0x02),
+            (0x420B, 0x03),
+        ] {
+            dma.bus_write(AddressU24::from(addr), value);
+        }
+        dma.update_state();
+
+        let (transfers, duration) = dma.pending_transfers(4, 8).unwrap();
+        let offsets: Vec<u64> = transfers.iter().map(|(offset, _, _)| *offset).collect();
+        // 4 cycles to sync, 8 cycles DMA overhead and 8 cycles overhead per channel
+        assert_eq!(offsets, vec![20, 28, 36, 52, 60]);
+        assert_eq!(duration, 72);
+        assert_eq!(transfers[1].2, AddressU24::new(0, 0x2119));
+        assert_eq!(transfers[3].2, AddressU24::new(0, 0x2122));
+    }
+}
@@ -4,6 +4,8 @@ pub mod devices;
 mod dma;
 mod multiplication;
 
+use std::fmt::Display;
+
 use dma::DmaController;
 use log::trace;
 
@@ -26,6 +28,43 @@ pub enum MainBusEvent {
     Write(AddressU24, u8),
 }
 
+/// Event shown in the per-frame event viewer, with the time at which it happened.
+///
+/// HDMA is not implemented, so HDMA transfers are not recorded. Only writes to HDMAEN are, to
+/// show when a game relies on HDMA.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedEvent {
+    pub clock: ClockInfo,
+    pub kind: TimedEventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimedEventKind {
+    PpuWrite(AddressU24, u8),
+    ApuWrite(AddressU24, u8),
+    /// DMA transfer of the given number of bytes.
+    Dma(usize),
+    /// Write to HDMAEN enabling the given HDMA channels.
+    HdmaEnable(u8),
+    Nmi,
+    Irq,
+}
+
+impl Display for TimedEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimedEventKind::PpuWrite(addr, value) => write!(f, "PPU write {addr} = ${value:02X}"),
+            TimedEventKind::ApuWrite(addr, value) => write!(f, "APU write {addr} = ${value:02X}"),
+            TimedEventKind::Dma(length) => write!(f, "DMA of {length} bytes"),
+            TimedEventKind::HdmaEnable(channels) => {
+                write!(f, "HDMAEN = ${channels:02X} (HDMA not implemented)")
+            }
+            TimedEventKind::Nmi => write!(f, "NMI"),
+            TimedEventKind::Irq => write!(f, "IRQ"),
+        }
+    }
+}
+
 pub struct MainBusImpl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> {
     pub(crate) ppu: PpuT,
     pub(crate) apu: ApuT,
@@ -42,6 +81,7 @@ pub struct MainBusImpl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> {
     mapping_mode: MappingMode,
 
     debug_event_collector: DebugEventCollectorRef<MainBusEvent>,
+    timed_event_collector: DebugEventCollectorRef<TimedEvent>,
 }
 
 impl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> MainBusImpl<PpuT, ApuT> {
@@ -62,6 +102,7 @@ impl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> MainBusImpl<PpuT, ApuT> {
             apu,
             multiplication: MultiplicationUnit::new(),
             debug_event_collector: DebugEventCollectorRef(debugger.clone()),
+            timed_event_collector: DebugEventCollectorRef(debugger.clone()),
             joy1: 0,
             joy2: 0,
             mapping_mode: cartridge.header.mapping_mode,
@@ -134,9 +175,24 @@ impl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> MainBusImpl<PpuT, ApuT> {
             MemoryBlock::Rom(offset) => self.rom[offset] = value,
             MemoryBlock::Sram(offset) => self.sram[offset] = value,
             MemoryBlock::Register => match addr.offset {
-                0x2100..=0x213F => self.ppu.write(addr, value),
-                0x2140..=0x217F => self.apu.write(addr, value),
-                0x420B | 0x420C | 0x4300..=0x43FF => self.dma_controller.bus_write(addr, value),
+                0x2133 => {
+                    self.on_timed_event(TimedEventKind::PpuWrite(addr, value));
+                    self.clock.bus_write(addr, value);
+                    self.ppu.write(addr, value);
+                }
+                0x2100..=0x213F => {
+                    self.on_timed_event(TimedEventKind::PpuWrite(addr, value));
+                    self.ppu.write(addr, value);
+                }
+                0x2140..=0x217F => {
+                    self.on_timed_event(TimedEventKind::ApuWrite(addr, value));
+                    self.apu.write(addr, value);
+                }
+                0x420C => {
+                    self.on_timed_event(TimedEventKind::HdmaEnable(value));
+                    self.dma_controller.bus_write(addr, value);
+                }
+                0x420B | 0x4300..=0x43FF => self.dma_controller.bus_write(addr, value),
                 0x4202..=0x4206 => self.multiplication.bus_write(addr, value),
                 0x4200 | 0x4207..=0x420A | 0x4210..=0x4212 => self.clock.bus_write(addr, value),
                 _ => {
@@ -156,6 +212,13 @@ impl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> MainBusImpl<PpuT, ApuT> {
         self.joy2 = joy2;
     }
 
+    fn on_timed_event(&self, kind: TimedEventKind) {
+        self.timed_event_collector.on_event(TimedEvent {
+            clock: self.clock.clock_info(),
+            kind,
+        });
+    }
+
     #[inline]
     fn memory_map(&self, addr: AddressU24) -> MemoryBlock {
         // TODO: Unnecessary branch on each cpu cycle
@@ -170,11 +233,19 @@ impl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> MainBusImpl<PpuT, ApuT> {
             .dma_controller
             .pending_transfers(self.clock_info().master_clock, self.clock_speed)
         {
-            self.clock.advance_master_clock(duration);
-            for (source, destination) in transfers {
+            self.on_timed_event(TimedEventKind::Dma(transfers.len()));
+            // Advance the clock to the time of each transfer, so writes happen and are recorded
+            // at the time they take place within the DMA.
+            let mut elapsed = 0;
+            for (offset, source, destination) in transfers {
+                self.clock.advance_master_clock(offset - elapsed);
+                self.ppu.update_clock(self.clock.clock_info());
+                self.apu.update_clock(self.clock.clock_info());
+                elapsed = offset;
                 let value = self.bus_read(source);
                 self.bus_write(destination, value);
             }
+            self.clock.advance_master_clock(duration - elapsed);
         }
         self.dma_controller.update_state();
 
@@ -229,11 +300,19 @@ impl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> Bus<AddressU24> for MainBusImpl<Ppu
 
 impl<PpuT: BusDeviceU24, ApuT: BusDeviceU24> MainBus for MainBusImpl<PpuT, ApuT> {
     fn consume_nmi_interrupt(&mut self) -> bool {
-        self.clock.consume_nmi_interrupt()
+        let nmi = self.clock.consume_nmi_interrupt();
+        if nmi {
+            self.on_timed_event(TimedEventKind::Nmi);
+        }
+        nmi
     }
 
     fn consume_timer_interrupt(&mut self) -> bool {
-        self.clock.consume_timer_interrupt()
+        let irq = self.clock.consume_timer_interrupt();
+        if irq {
+            self.on_timed_event(TimedEventKind::Irq);
+        }
+        irq
     }
 
     fn clock_info(&self) -> ClockInfo {
@@ -385,25 +464,13 @@ mod tests {
     use image::RgbaImage;
 
     use super::*;
+    use crate::common::test_util::compare_image_against_golden;
+    use crate::debugger::Debugger;
 
     fn test_dir() -> PathBuf {
         PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/main_bus")
     }
 
-    fn compare_to_golden(image: &RgbaImage, path_prefix: &Path) {
-        let golden_path = path_prefix.with_extension("png");
-        if golden_path.exists() {
-            let golden: RgbaImage = image::open(&golden_path).unwrap().into_rgba8();
-            if golden != *image {
-                let actual_path = golden_path.with_extension("actual.png");
-                image.save(&actual_path).unwrap();
-                panic!("Image does not match golden. See {:?}", actual_path);
-            }
-        } else {
-            image.save(golden_path).unwrap();
-        }
-    }
-
     pub fn gradient(color: [u8; 4], value: usize, max_value: usize) -> Rgba<u8> {
         let value = value.min(max_value);
         let factor = (value as f32 / max_value as f32) * 0.8 + 0.2;
@@ -435,7 +502,71 @@ mod tests {
                 image.put_pixel(bank as u32, offset as u32, color);
             }
         }
-        compare_to_golden(&image, path_prefix);
+        compare_image_against_golden(&image, path_prefix);
+    }
+
+    /// Records the master clock at which each byte is written to the device.
+    #[derive(Default)]
+    struct RecordingDevice {
+        clock: u64,
+        writes: Vec<(u64, u8)>,
+    }
+
+    impl BusDeviceU24 for RecordingDevice {
+        const NAME: &'static str = "Recording";
+
+        fn peek(&self, _addr: AddressU24) -> Option<u8> {
+            None
+        }
+
+        fn read(&mut self, _addr: AddressU24) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _addr: AddressU24, value: u8) {
+            self.writes.push((self.clock, value));
+        }
+
+        fn update_clock(&mut self, new_clock: ClockInfo) {
+            self.clock = new_clock.master_clock;
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn test_dma_writes_are_timed_per_transfer() {
+        let mut bus = MainBusImpl::new(
+            &Cartridge::default(),
+            RecordingDevice::default(),
+            RecordingDevice::default(),
+            Debugger::new(),
+        );
+        for i in 0..3 {
+            bus.cycle_write_u8(AddressU24::new(0, i), 0x10 + i as u8);
+        }
+        // Channel 0 transfers 3 bytes from WRAM 0x0000 to VMDATAL
+        for (addr, value) in [
+            (0x4300, 0x00),
+            (0x4301, 0x18),
+            (0x4305, 0x03),
+            (0x420B, 0x01),
+        ] {
+            bus.cycle_write_u8(AddressU24::from(addr), value);
+        }
+        // The DMA starts on the cycle after the MDMAEN write
+        bus.cycle_io();
+        bus.cycle_io();
+
+        // Each byte reaches the PPU 8 master cycles after the previous one, rather than all
+        // at the end of the DMA.
+        let writes = &bus.ppu.writes;
+        assert_eq!(
+            writes.iter().map(|(_, value)| *value).collect::<Vec<_>>(),
+            vec![0x10, 0x11, 0x12]
+        );
+        assert_eq!(writes[1].0 - writes[0].0, 8);
+        assert_eq!(writes[2].0 - writes[1].0, 8);
     }
 
     #[test]
@@ -0,0 +1,281 @@
+//! Recording of the video and audio output of the emulator.
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use hound::WavWriter;
+
+use crate::apu::AudioBuffer;
+use crate::apu::MASTER_CLOCK_FREQUENCY;
+use crate::common::image::Rgb15;
+use crate::common::image::Rgba32;
+use crate::components::ppu::Framebuffer;
+use crate::components::s_dsp::WAV_SPEC;
+
+/// Average length of a frame in master cycles. Frames alternate between 357368 and 357364
+/// cycles.
+const MASTER_CYCLES_PER_FRAME: u64 = 357366;
+
+/// Size of the largest frame the PPU outputs, in hires interlace mode with overscan.
+pub const VIDEO_WIDTH: u32 = 512;
+pub const VIDEO_HEIGHT: u32 = 478;
+
+/// Receives every video frame and audio buffer handed out by the system, see
+/// [crate::SystemImpl::set_frame_sink].
+pub trait FrameSink: Send {
+    fn video_frame(&mut self, frame: &Framebuffer) -> Result<()>;
+    fn audio_samples(&mut self, samples: &AudioBuffer) -> Result<()>;
+
+    /// Called when the sink is removed from the system to flush any pending output.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the audio output to a WAV file while it is generated.
+pub struct WavRecorder {
+    writer: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl WavRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            writer: Some(WavWriter::create(path, WAV_SPEC)?),
+        })
+    }
+}
+
+impl FrameSink for WavRecorder {
+    fn video_frame(&mut self, _frame: &Framebuffer) -> Result<()> {
+        Ok(())
+    }
+
+    fn audio_samples(&mut self, samples: &AudioBuffer) -> Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            for sample in samples.iter() {
+                writer.write_sample(*sample)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.finalize()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes video to a Y4M file and audio to a WAV file next to it.
+///
+/// Frames are stored uncompressed as full range YCbCr 4:4:4, which converts back to the exact
+/// 15-bit SNES colors. The video is always [VIDEO_WIDTH]x[VIDEO_HEIGHT] so the output mode can
+/// change during the recording without losing pixels, see [Y4mWavRecorder::video_frame].
+pub struct Y4mWavRecorder {
+    video: BufWriter<File>,
+    audio: WavRecorder,
+}
+
+impl Y4mWavRecorder {
+    /// Creates `path` with the extensions .y4m and .wav.
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut video = BufWriter::new(File::create(path.with_extension("y4m"))?);
+        writeln!(
+            video,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444 XCOLORRANGE=FULL",
+            VIDEO_WIDTH, VIDEO_HEIGHT, MASTER_CLOCK_FREQUENCY, MASTER_CYCLES_PER_FRAME
+        )?;
+        Ok(Self {
+            video,
+            audio: WavRecorder::create(&path.with_extension("wav"))?,
+        })
+    }
+}
+
+impl FrameSink for Y4mWavRecorder {
+    /// Lowres and non-interlaced frames are scaled up by a factor of 2 with nearest neighbor.
+    /// Frames without overscan are 448 lines after scaling and padded with black at the bottom,
+    /// which keeps the picture at the same position as in overscan mode.
+    fn video_frame(&mut self, frame: &Framebuffer) -> Result<()> {
+        let scale_x = (VIDEO_WIDTH / frame.width()).max(1);
+        let scale_y = (VIDEO_HEIGHT / frame.height()).max(1);
+        let black = rgb15_to_ycbcr(Rgb15(0));
+        let plane_size = (VIDEO_WIDTH * VIDEO_HEIGHT) as usize;
+        let mut planes = vec![0; plane_size * 3];
+        for y in 0..VIDEO_HEIGHT {
+            for x in 0..VIDEO_WIDTH {
+                let (frame_x, frame_y) = (x / scale_x, y / scale_y);
+                let [luma, cb, cr] = if frame_x < frame.width() && frame_y < frame.height() {
+                    rgb15_to_ycbcr(frame[(frame_x, frame_y)])
+                } else {
+                    black
+                };
+                let idx = (y * VIDEO_WIDTH + x) as usize;
+                planes[idx] = luma;
+                planes[plane_size + idx] = cb;
+                planes[plane_size * 2 + idx] = cr;
+            }
+        }
+        self.video.write_all(b"FRAME\n")?;
+        self.video.write_all(&planes)?;
+        Ok(())
+    }
+
+    fn audio_samples(&mut self, samples: &AudioBuffer) -> Result<()> {
+        self.audio.audio_samples(samples)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.video.flush()?;
+        self.audio.finish()
+    }
+}
+
+/// Converts to full range BT.601 YCbCr.
+fn rgb15_to_ycbcr(pixel: Rgb15) -> [u8; 3] {
+    let Rgba32([r, g, b, _]) = pixel.into();
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+    let cb = 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b;
+    let cr = 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b;
+    [luma, cb, cr].map(|value| value.round().clamp(0.0, 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::SyncSystem;
+
+    fn ycbcr_to_rgb([luma, cb, cr]: [u8; 3]) -> [f32; 3] {
+        let (luma, cb, cr) = (luma as f32, cb as f32 - 128.0, cr as f32 - 128.0);
+        [
+            luma + 1.402 * cr,
+            luma - 0.344136 * cb - 0.714136 * cr,
+            luma + 1.772 * cb,
+        ]
+    }
+
+    #[test]
+    fn test_color_conversion_is_lossless() {
+        for value in 0..0x8000 {
+            let pixel = Rgb15(value);
+            let Rgba32([r, g, b, _]) = pixel.into();
+            let decoded = ycbcr_to_rgb(rgb15_to_ycbcr(pixel));
+            // Each decoded channel must be closer to the original than to the neighboring
+            // 5-bit levels, which are about 8 apart.
+            for (expected, actual) in [r, g, b].into_iter().zip(decoded) {
+                assert!(
+                    (expected as f32 - actual).abs() < 4.0,
+                    "{pixel:?}: {expected} decoded as {actual}"
+                );
+            }
+        }
+    }
+
+    fn read_wav(path: &Path) -> Vec<i16> {
+        hound::WavReader::open(path)
+            .unwrap()
+            .samples()
+            .map(|s| s.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_wav_recorder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording.wav");
+        let mut recorder = WavRecorder::create(&path).unwrap();
+
+        let mut audio = AudioBuffer::new();
+        audio.push_sample(100);
+        audio.push_sample(-100);
+        recorder.audio_samples(&audio).unwrap();
+        recorder.video_frame(&Framebuffer::new(256, 224)).unwrap();
+        recorder.audio_samples(&audio).unwrap();
+        recorder.finish().unwrap();
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec(), WAV_SPEC);
+        assert_eq!(read_wav(&path), vec![100, -100, 100, -100]);
+    }
+
+    #[test]
+    fn test_y4m_wav_recorder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording");
+        let mut recorder = Y4mWavRecorder::create(&path).unwrap();
+
+        let mut frame = Framebuffer::new(256, 224);
+        frame[(1, 0)] = Rgb15(0x7FFF);
+        recorder.video_frame(&frame).unwrap();
+        // Switching to hires interlace must not lose any pixels.
+        let mut hires = Framebuffer::new(512, 448);
+        hires[(1, 0)] = Rgb15(0x7FFF);
+        hires[(2, 1)] = Rgb15(0x7FFF);
+        recorder.video_frame(&hires).unwrap();
+
+        let mut audio = AudioBuffer::new();
+        audio.push_sample(100);
+        audio.push_sample(-100);
+        recorder.audio_samples(&audio).unwrap();
+        recorder.finish().unwrap();
+
+        let video = std::fs::read(path.with_extension("y4m")).unwrap();
+        let header = b"YUV4MPEG2 W512 H478 F21477272:357366 Ip A1:1 C444 XCOLORRANGE=FULL\n";
+        assert!(video.starts_with(header));
+        let frame_size = 6 + 512 * 478 * 3;
+        assert_eq!(video.len(), header.len() + 2 * frame_size);
+        let white = rgb15_to_ycbcr(Rgb15(0x7FFF))[0];
+        let luma_row = |frame: usize, y: usize| {
+            let start = header.len() + frame * frame_size + 6 + y * 512;
+            &video[start..start + 4]
+        };
+        assert_eq!(luma_row(0, 0), &[0, 0, white, white]);
+        assert_eq!(luma_row(0, 1), &[0, 0, white, white]);
+        assert_eq!(luma_row(1, 0), &[0, white, 0, 0]);
+        assert_eq!(luma_row(1, 1), &[0, 0, white, 0]);
+        // Lines below the 448 line picture are padded with black.
+        assert_eq!(luma_row(0, 477), &[0, 0, 0, 0]);
+
+        assert_eq!(read_wav(&path.with_extension("wav")), vec![100, -100]);
+    }
+
+    /// Fails on every write and records whether it was finished.
+    struct FailingSink {
+        finished: Arc<AtomicBool>,
+    }
+
+    impl FrameSink for FailingSink {
+        fn video_frame(&mut self, _frame: &Framebuffer) -> Result<()> {
+            anyhow::bail!("video write failed")
+        }
+
+        fn audio_samples(&mut self, _samples: &AudioBuffer) -> Result<()> {
+            anyhow::bail!("audio write failed")
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            self.finished.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_failing_sink_is_finished() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut system = SyncSystem::new();
+        system.set_frame_sink(Box::new(FailingSink {
+            finished: finished.clone(),
+        }));
+        system.swap_audio_buffer(&mut AudioBuffer::new());
+        assert!(!system.has_frame_sink());
+        assert!(finished.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,257 @@
+//! Runs the APU test ROMs listed in tests/apu_test_roms/tests.txt and prints a summary table.
+//!
+//! Test ROMs detect pass or fail by themselves and report it on screen or in memory. The
+//! manifest describes for each ROM how long to run it and which screen or memory signature
+//! indicates that it passed.
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use image::RgbaImage;
+use sres_emulator::common::bus::Bus;
+use sres_emulator::common::logging;
+use sres_emulator::components::cartridge::Cartridge;
+use sres_emulator::components::ppu::Framebuffer;
+use sres_emulator::SyncSystem;
+
+#[test]
+pub fn test_apu_test_roms() {
+    logging::test_init(false);
+    let manifest = std::fs::read_to_string(test_dir().join("tests.txt")).unwrap();
+    let results: Vec<(TestCase, TestResult)> = manifest
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let test = TestCase::parse(line).unwrap();
+            let result = test.run();
+            (test, result)
+        })
+        .collect();
+
+    let table = summary_table(&results);
+    println!("{table}");
+    let failed = results
+        .iter()
+        .filter(|(_, result)| matches!(result, TestResult::Fail(_)))
+        .count();
+    assert_eq!(failed, 0, "{failed} APU test ROMs failed:\n{table}");
+}
+
+#[test]
+pub fn test_parse_invalid_checks() {
+    for check in [
+        "aram:0000=",
+        "wram:0000=",
+        "aram:FFFF=0000",
+        "aram:0000=123",
+        "aram=00",
+        "vram:0000=00",
+    ] {
+        assert!(Check::parse(check).is_err(), "{check} was accepted");
+    }
+    assert!(Check::parse("aram:FFFF=00").is_ok());
+    assert!(Check::parse("wram:FFFF=0000").is_ok());
+}
+
+struct TestCase {
+    name: String,
+    rom: PathBuf,
+    frames: u64,
+    checks: Vec<Check>,
+}
+
+enum Check {
+    Aram(u16, Vec<u8>),
+    Wram(u16, Vec<u8>),
+    Screen(PathBuf),
+}
+
+enum TestResult {
+    Pass,
+    Fail(String),
+}
+
+impl TestCase {
+    fn parse(line: &str) -> Result<Self> {
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(rom), Some(frames)) = (fields.next(), fields.next(), fields.next())
+        else {
+            bail!("Expected <name> <rom> <frames> <check>...: {line}");
+        };
+        Ok(Self {
+            name: name.to_string(),
+            rom: test_dir().join(rom),
+            frames: frames.parse()?,
+            checks: fields.map(Check::parse).collect::<Result<_>>()?,
+        })
+    }
+
+    fn run(&self) -> TestResult {
+        if !self.rom.exists() {
+            return TestResult::Fail(format!("{} not found", self.rom.display()));
+        }
+        let cartridge = match Cartridge::with_sfc_file(&self.rom) {
+            Ok(cartridge) => cartridge,
+            Err(err) => return TestResult::Fail(format!("Failed to load rom: {err}")),
+        };
+        let mut system = SyncSystem::with_cartridge(&cartridge);
+        system.execute_frames(self.frames);
+        let mut frame = Framebuffer::default();
+        system.swap_video_frame(&mut frame);
+
+        let failures: Vec<String> = self
+            .checks
+            .iter()
+            .filter_map(|check| check.verify(&system, &frame).err())
+            .map(|err| err.to_string())
+            .collect();
+        if failures.is_empty() {
+            TestResult::Pass
+        } else {
+            TestResult::Fail(failures.join("; "))
+        }
+    }
+}
+
+impl Check {
+    fn parse(check: &str) -> Result<Self> {
+        let (kind, args) = check
+            .split_once(':')
+            .with_context(|| format!("Invalid check: {check}"))?;
+        let memory_signature = || -> Result<(u16, Vec<u8>)> {
+            let (addr, bytes) = args
+                .split_once('=')
+                .with_context(|| format!("Expected <addr>=<hex bytes>: {check}"))?;
+            let bytes: Vec<u8> = (0..bytes.len())
+                .step_by(2)
+                .map(|idx| {
+                    Ok(u8::from_str_radix(
+                        bytes.get(idx..idx + 2).unwrap_or("?"),
+                        16,
+                    )?)
+                })
+                .collect::<Result<_>>()?;
+            let addr = u16::from_str_radix(addr, 16)?;
+            if bytes.is_empty() {
+                bail!("Expected at least one byte: {check}");
+            }
+            Ok((addr, bytes))
+        };
+        Ok(match kind {
+            "aram" => {
+                let (addr, bytes) = memory_signature()?;
+                if addr as usize + bytes.len() > ARAM_SIZE {
+                    bail!("Signature exceeds the end of ARAM: {check}");
+                }
+                Check::Aram(addr, bytes)
+            }
+            "wram" => {
+                let (addr, bytes) = memory_signature()?;
+                if addr as usize + bytes.len() > WRAM_SIZE {
+                    bail!("Signature exceeds the end of WRAM: {check}");
+                }
+                Check::Wram(addr, bytes)
+            }
+            "screen" => Check::Screen(test_dir().join(args)),
+            _ => bail!("Unknown check: {check}"),
+        })
+    }
+
+    fn verify(&self, system: &SyncSystem, frame: &Framebuffer) -> Result<()> {
+        match self {
+            Check::Aram(addr, expected) => {
+                let start = *addr as usize;
+                let debug = system.debug();
+                let actual = debug.apu().ram()[start..start + expected.len()].to_vec();
+                compare_memory("aram", start as u32, &actual, expected)
+            }
+            Check::Wram(addr, expected) => {
+                let start = 0x7E0000 + *addr as u32;
+                let actual = system
+                    .cpu
+                    .bus
+                    .peek_range(start..=start + expected.len() as u32 - 1);
+                compare_memory("wram", start, &actual, expected)
+            }
+            Check::Screen(path) => {
+                if !path.exists() {
+                    bail!("pass screen {} not found", path.display());
+                }
+                let expected = image::open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?
+                    .into_rgba8();
+                let actual: RgbaImage = frame.to_rgba();
+                if actual != expected {
+                    let actual_path = path.with_extension("actual.png");
+                    actual.save(&actual_path)?;
+                    bail!("screen does not match, see {}", actual_path.display());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn compare_memory(name: &str, addr: u32, actual: &[u8], expected: &[u8]) -> Result<()> {
+    if actual != expected {
+        bail!(
+            "{name} at ${addr:04X} is {}, expected {}",
+            format_hex(actual),
+            format_hex(expected)
+        );
+    }
+    Ok(())
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl Display for TestResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestResult::Pass => write!(f, "pass"),
+            TestResult::Fail(_) => write!(f, "FAIL"),
+        }
+    }
+}
+
+fn summary_table(results: &[(TestCase, TestResult)]) -> String {
+    let name_width = results
+        .iter()
+        .map(|(test, _)| test.name.len())
+        .max()
+        .unwrap_or_default()
+        .max(4);
+    let mut table = format!("{:name_width$}  {:7}  details\n", "test", "result");
+    for (test, result) in results {
+        let details = match result {
+            TestResult::Pass => "",
+            TestResult::Fail(details) => details,
+        };
+        table += &format!(
+            "{:name_width$}  {:7}  {details}\n",
+            test.name,
+            result.to_string()
+        );
+    }
+    let count = |f: fn(&TestResult) -> bool| results.iter().filter(|(_, r)| f(r)).count();
+    table += &format!(
+        "{} passed, {} failed",
+        count(|r| matches!(r, TestResult::Pass)),
+        count(|r| matches!(r, TestResult::Fail(_))),
+    );
+    table
+}
+
+/// Memory signatures must end within the APU RAM and WRAM at $7E0000-$7FFFFF.
+const ARAM_SIZE: usize = 0x10000;
+const WRAM_SIZE: usize = 0x20000;
+
+fn test_dir() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/apu_test_roms"))
+}
@@ -0,0 +1,149 @@
+// SNES SPC700 CPU & DSP Test (SPC Code):
+// Runs SPC700 instructions with known results and stores A, Y & PSW of each test at $0020.
+// Then plays a BRR block that ends without loop and stores ENDX, ENVX & ENDX after clearing
+// it at $0048. Writes $01 to $0010 once all tests ran.
+//
+// Expected results:
+//   $0020: 80 00 C8  adc $7F + $01          = $80, N V H
+//   $0023: 01 00 09  adc $FF + $01 + C      = $01, H C
+//   $0026: FF 00 80  sbc $00 - $01          = $FF, N
+//   $0029: 40 00 03  cmp $40 with $40       Z C
+//   $002C: A8 03 00  mul $12 * $34          = $03A8
+//   $002F: 12 03 08  div $0123 / $10        = $12 remainder $03, H
+//   $0032: C3 00 80  xcn $3C                = $C3, N
+//   $0035: 00 00 03  daa $9A                = $00, Z C
+//   $0038: 9A 00 80  das $00                = $9A, N
+//   $003B: 00 80 C8  addw $7FFF + $0001     = $8000, N V H
+//   $003E: 80 00 81  ror $01 with C         = $80, N C
+//   $0048: 01 00 00  ENDX set, ENVX 0 after the end block, ENDX cleared by a write
+arch snes.smp
+output "spc700_dsp.spc", create
+
+macro seek(variable offset) { // Set SPC700 Memory Map
+  origin (offset - SPCRAM)
+  base offset
+}
+
+include "../asm_lib/snes_spc700.inc" // Include SPC700 Definitions & Macros
+
+constant RESULTS = $20
+constant DSP_RESULTS = $48
+constant DONE = $10
+constant WORD = $18
+
+macro SETPSW(PSW) { // Set All Status Flags
+  lda #{PSW}
+  pha
+  plp
+}
+
+macro RESULT(ADDR) { // Store A, Y & PSW To ADDR..ADDR+2
+  sta {ADDR}
+  sty {ADDR} + 1
+  php
+  pla
+  sta {ADDR} + 2
+}
+
+seek(SPCRAM); Start:
+  SPC_INIT() // Run SPC700 Initialisation Routine
+
+  SETPSW(%00000000)
+  lda #$7F
+  ldy #$00
+  adc #$01
+  RESULT(RESULTS + 0)
+
+  SETPSW(%00000001) // Carry Set
+  lda #$FF
+  ldy #$00
+  adc #$01
+  RESULT(RESULTS + 3)
+
+  SETPSW(%00000001) // Carry Set (No Borrow)
+  lda #$00
+  ldy #$00
+  sbc #$01
+  RESULT(RESULTS + 6)
+
+  SETPSW(%00000000)
+  lda #$40
+  ldy #$00
+  cmp #$40
+  RESULT(RESULTS + 9)
+
+  SETPSW(%00000000)
+  lda #$34
+  ldy #$12
+  mul // YA = Y * A
+  RESULT(RESULTS + 12)
+
+  SETPSW(%00000000)
+  lda #$23
+  ldy #$01
+  ldx #$10
+  div // A = YA / X, Y = YA % X
+  RESULT(RESULTS + 15)
+
+  SETPSW(%00000000)
+  lda #$3C
+  ldy #$00
+  xcn // Exchange Nibbles Of A
+  RESULT(RESULTS + 18)
+
+  SETPSW(%00000000)
+  lda #$9A
+  ldy #$00
+  daa // Decimal Adjust A For Addition
+  RESULT(RESULTS + 21)
+
+  SETPSW(%00000000)
+  lda #$00
+  ldy #$00
+  das // Decimal Adjust A For Subtraction
+  RESULT(RESULTS + 24)
+
+  str WORD=#$01
+  str WORD + 1=#$00
+  SETPSW(%00000000)
+  lda #$FF
+  ldy #$7F
+  adw WORD // YA += Word
+  RESULT(RESULTS + 27)
+
+  SETPSW(%00000001) // Carry Set
+  lda #$01
+  ldy #$00
+  ror
+  RESULT(RESULTS + 30)
+
+  WDSP(DSP_DIR,sampleDIR >> 8) // Sample Directory Offset
+  WDSP(DSP_KOFF,$00)      // Reset Key Off Flags
+  WDSP(DSP_V0PITCHL,$00)  // Voice 0: Pitch (Lower Byte)
+  WDSP(DSP_V0PITCHH,$10)  // Voice 0: Pitch (Upper Byte)
+  WDSP(DSP_V0SRCN,0)      // Voice 0: Sample
+  WDSP(DSP_V0ADSR1,$00)   // Voice 0: ADSR Off, Use Gain
+  WDSP(DSP_V0GAIN,127)    // Voice 0: Fixed Gain
+  WDSP(DSP_KON,%00000001) // Play Voice 0
+  SPCWaitMS(8) // Wait 8 ms
+
+  WDSP_REG(DSP_ENDX)
+  lda REG_DSPDATA
+  sta DSP_RESULTS + 0
+  WDSP_REG(DSP_V0ENVX)
+  lda REG_DSPDATA
+  sta DSP_RESULTS + 1
+  WDSP(DSP_ENDX,$00) // Any Write Clears ENDX
+  lda REG_DSPDATA
+  sta DSP_RESULTS + 2
+
+  str DONE=#$01
+
+Loop:
+  jmp Loop
+
+seek($0400); sampleDIR:
+  dw BRRSample, BRRSample // BRR Sample Offset, Loop Point
+
+BRRSample: // Silent Block With End Flag & Without Loop Flag
+  db $01, $00, $00, $00, $00, $00, $00, $00, $00
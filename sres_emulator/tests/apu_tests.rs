@@ -7,6 +7,7 @@ use sres_emulator::apu::AudioBuffer;
 use sres_emulator::common::test_util::compare_wav_against_golden;
 use sres_emulator::common::util::format_memory;
 use sres_emulator::components::cartridge::Cartridge;
+use sres_emulator::components::s_dsp::DspTiming;
 use sres_emulator::debugger::EventFilter;
 use sres_emulator::System;
 
@@ -38,6 +39,41 @@ pub fn test_play_brr_sample() {
     compare_wav_against_golden(&samples.into_vec(), &path_prefix)
 }
 
+#[test]
+pub fn test_play_brr_sample_cycle_timing() {
+    let play_brr_sample = |timing: DspTiming| {
+        let root_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let rom_path = root_dir.join("tests/apu_tests/play_brr_sample.sfc");
+        let mut system = System::with_cartridge(&Cartridge::with_sfc_file(&rom_path).unwrap());
+        system.set_dsp_timing(timing);
+        system.debug_until(EventFilter::Spc700ProgramCounter(0x02e9..0x02ea));
+        let mut samples = AudioBuffer::new();
+        system.swap_audio_buffer(&mut samples);
+        system.execute_for_audio_samples(NUM_SAMPLES + DELAY);
+        system.swap_audio_buffer(&mut samples);
+        samples.into_vec()
+    };
+    const NUM_SAMPLES: usize = 7936; // Length of the play_brr_sample sample
+                                     // KON is latched later by the cycle-stepped DSP, which delays the whole sample.
+    const DELAY: usize = 3;
+    let expected = play_brr_sample(DspTiming::Sample);
+    let actual = play_brr_sample(DspTiming::Cycle);
+
+    // Envelope steps follow the global rate counter instead of the key on, so each step happens
+    // 2 samples later relative to the delayed sample. The attack (rate 10) steps every 20
+    // samples until it completes at sample 1240, followed by a single decay step.
+    let envelope_steps: Vec<usize> = (20..=1240).step_by(20).chain([1242]).collect();
+    for i in 0..NUM_SAMPLES {
+        if envelope_steps
+            .iter()
+            .any(|step| (*step..*step + 2).contains(&i))
+        {
+            continue;
+        }
+        assert_eq!(actual[i + DELAY], expected[i], "Sample {i} does not match");
+    }
+}
+
 #[test]
 pub fn test_play_noise() {
     let root_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -55,12 +91,13 @@ pub fn test_play_noise() {
     let actual_program = system.debug().apu().ram()[0x0200..(0x0200 + spc_program.len())].to_vec();
     assert_eq!(format_memory(&actual_program), format_memory(&spc_program));
 
-    // Run until "Kick" info has been written into Voice 0
+    // Run until "Kick" info has been written into Voice 0. The voice is silent since it has
+    // been released by the KOFF write during initialization.
     system.debug_until(EventFilter::Spc700ProgramCounter(0x02dd..0x02de));
 
     assert_eq!(
         system.debug().apu().dsp().voice(0),
-        "vol:127/127 pitch:0 adsr:(14,0,7,22) src:$00 env:2 out:-1".to_string()
+        "vol:127/127 pitch:0 adsr:(14,0,7,22) src:$00 env:0 out:0".to_string()
     );
 }
 
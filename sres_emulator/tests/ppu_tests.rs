@@ -6,67 +6,80 @@
 //! Some tests will use snapshots of the PPU state to run testing in isolation of the CPU
 //! behavior and in absence of ROM files.
 use std::collections::HashMap;
-use std::path::Path;
 use std::path::PathBuf;
 
 use image::RgbaImage;
-use sres_emulator::common::image::Image;
-use sres_emulator::common::image::Rgba32;
+use sres_emulator::common::debug_events::DebugEventCollectorRef;
 use sres_emulator::common::logging;
+use sres_emulator::common::test_util::compare_image_against_golden;
 use sres_emulator::components::cartridge::Cartridge;
 use sres_emulator::components::ppu::BackgroundId;
 use sres_emulator::components::ppu::Framebuffer;
 use sres_emulator::components::ppu::Ppu;
+use sres_emulator::components::ppu::RenderMode;
 use sres_emulator::components::ppu::VramRenderSelection;
+use sres_emulator::debugger::Debugger;
 use sres_emulator::System;
 
+/// Most krom test roms fade in by increasing the INIDISP brightness by one step per frame after
+/// setup. Full brightness is reached after 18 frames.
+const KROM_FADE_IN_FRAMES: u64 = 20;
+
 #[test]
 pub fn test_krom_hdma_redspace() {
     run_framebuffer_test("krom_hdma_redspace", 10);
 }
 
+#[test]
+pub fn test_krom_hdma_redspace_segmented() {
+    // Segmented rendering must match the scanline renderer when no registers change mid-scanline.
+    // None of the test roms write PPU registers mid-scanline (HDMA is not implemented), that is
+    // covered by the `test_segmented_render_mode` unit test.
+    run_framebuffer_test_with_render_mode("krom_hdma_redspace", 10, RenderMode::Segmented);
+}
+
 #[test]
 pub fn test_krom_rings() {
-    run_framebuffer_test("krom_rings", 10);
+    run_framebuffer_test("krom_rings", KROM_FADE_IN_FRAMES);
 }
 
 #[test]
 pub fn test_krom_hello_world() {
-    run_framebuffer_test("krom_hello_world", 10);
+    run_framebuffer_test("krom_hello_world", KROM_FADE_IN_FRAMES);
 }
 
 #[test]
 pub fn test_krom_bgmap_2bpp() {
-    run_framebuffer_test("krom_bgmap_2bpp", 10);
+    run_framebuffer_test("krom_bgmap_2bpp", KROM_FADE_IN_FRAMES);
 }
 
 #[test]
 pub fn test_krom_bgmap_4bpp() {
-    run_framebuffer_test("krom_bgmap_4bpp", 10);
+    run_framebuffer_test("krom_bgmap_4bpp", KROM_FADE_IN_FRAMES);
 }
 
 #[test]
 pub fn test_krom_bgmap_8bpp() {
-    run_framebuffer_test("krom_bgmap_8bpp", 10);
+    run_framebuffer_test("krom_bgmap_8bpp", KROM_FADE_IN_FRAMES);
 }
 
 #[test]
 pub fn test_krom_blend_hicolor_3840() {
-    run_framebuffer_test("krom_blend_hicolor_3840", 10);
+    run_framebuffer_test("krom_blend_hicolor_3840", KROM_FADE_IN_FRAMES);
 }
 
 #[test]
 pub fn test_krom_interlace_rpg() {
-    // Note: Interlacing or high-res is not implemented and used by this test rom.
-    // However it's the only test rom I have available to test sprite rendering.
-    run_framebuffer_test("krom_interlace_rpg", 10);
+    // Note: This test rom uses interlacing and high-res, but it's also the only test rom I have
+    // available to test sprite rendering.
+    run_framebuffer_test("krom_interlace_rpg", KROM_FADE_IN_FRAMES);
 }
 
 #[test]
 pub fn test_colourmath() {
     logging::test_init(true);
 
-    let rom_path = test_dir().join(format!("colourmath.sfc"));
+    let rom_path = test_dir().join("colourmath.sfc");
     let mut system = System::with_cartridge(&Cartridge::with_sfc_file(&rom_path).unwrap());
     system.execute_frames(30);
 
@@ -76,7 +89,7 @@ pub fn test_colourmath() {
 
         let mut video_frame = Framebuffer::default();
         system.swap_video_frame(&mut video_frame);
-        compare_to_golden(&video_frame.to_rgba::<TestImageImpl>(), &framebuffer_path);
+        compare_image_against_golden(&video_frame.to_rgba::<RgbaImage>(), &framebuffer_path);
 
         // Advance to next test by simulating a button press.
         system.update_joypads(64, 0);
@@ -88,16 +101,25 @@ pub fn test_colourmath() {
 
 /// Renders the framebuffer at `frame` and compares against previously stored golden image.
 fn run_framebuffer_test(test_name: &str, frame: u64) -> System {
+    run_framebuffer_test_with_render_mode(test_name, frame, RenderMode::Scanline)
+}
+
+fn run_framebuffer_test_with_render_mode(
+    test_name: &str,
+    frame: u64,
+    render_mode: RenderMode,
+) -> System {
     logging::test_init(true);
 
     let rom_path = test_dir().join(format!("{test_name}.sfc"));
     let mut system = System::with_cartridge(&Cartridge::with_sfc_file(&rom_path).unwrap());
+    system.set_ppu_render_mode(render_mode);
     system.execute_frames(frame);
     let framebuffer_path = test_dir().join(format!("{test_name}-framebuffer"));
 
     let mut video_frame = Framebuffer::default();
     system.swap_video_frame(&mut video_frame);
-    compare_to_golden(&video_frame.to_rgba::<TestImageImpl>(), &framebuffer_path);
+    compare_image_against_golden(&video_frame.to_rgba::<RgbaImage>(), &framebuffer_path);
     system
 }
 
@@ -105,8 +127,8 @@ fn run_framebuffer_test(test_name: &str, frame: u64) -> System {
 /// stored golden images.
 #[test]
 pub fn test_krom_interlace_rpg_debug_render() {
-    // Note: Interlacing or high-res is not implemented and used by this test rom.
-    // However it's the only test rom I have available to test sprite rendering.
+    // Note: This test rom uses interlacing and high-res, but it's also the only test rom I have
+    // available to test sprite rendering.
     logging::test_init(true);
 
     let rom_path = test_dir().join("krom_interlace_rpg.sfc");
@@ -117,20 +139,20 @@ pub fn test_krom_interlace_rpg_debug_render() {
 
     // Debug render sprite 0
     let sprite_path = test_dir().join("krom_interlace_rpg-sprite0");
-    compare_to_golden(&ppu.render_sprite(0), &sprite_path);
+    compare_image_against_golden(&ppu.render_sprite(0), &sprite_path);
 
     // Debug render BG0
     let background_path = test_dir().join("krom_interlace_rpg-bg0");
-    compare_to_golden(&ppu.render_background(BackgroundId::BG1), &background_path);
+    compare_image_against_golden(&ppu.render_background(BackgroundId::BG1), &background_path);
 
     // Debug render VRAM
     let vram_bg0_path = test_dir().join("krom_interlace_rpg-vram-bg1");
-    compare_to_golden(
+    compare_image_against_golden(
         &ppu.render_vram(VramRenderSelection::Background(BackgroundId::BG1)),
         &vram_bg0_path,
     );
     let vram_sprite_path = test_dir().join("krom_interlace_rpg-vram-sprite");
-    compare_to_golden(
+    compare_image_against_golden(
         &ppu.render_vram(VramRenderSelection::Sprite0),
         &vram_sprite_path,
     );
@@ -190,15 +212,15 @@ fn test_tloz_game() {
 fn run_snapshot_framebuffer_test(snapshot_name: &str) {
     logging::test_init(true);
 
-    let mut ppu = Ppu::new();
+    let mut ppu = Ppu::new(DebugEventCollectorRef(Debugger::new()));
     ppu.load_state(&std::fs::read(test_dir().join(format!("{snapshot_name}.snapshot"))).unwrap())
         .unwrap();
     for scanline in 0..256 {
         ppu.draw_scanline(scanline);
     }
 
-    compare_to_golden(
-        &ppu.framebuffer().to_rgba::<TestImageImpl>(),
+    compare_image_against_golden(
+        &ppu.framebuffer().to_rgba::<RgbaImage>(),
         &test_dir().join(snapshot_name),
     );
 }
@@ -255,33 +277,3 @@ pub fn test_sprite_rendering() {
 fn test_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/ppu_tests")
 }
-
-fn compare_to_golden(image: &TestImageImpl, path_prefix: &Path) {
-    let golden_path = path_prefix.with_extension("png");
-    if golden_path.exists() {
-        let golden: RgbaImage = image::open(&golden_path).unwrap().into_rgba8();
-        if golden != image.inner {
-            let actual_path = golden_path.with_extension("actual.png");
-            image.inner.save(&actual_path).unwrap();
-            panic!("Image does not match golden. See {:?}", actual_path);
-        }
-    } else {
-        image.inner.save(golden_path).unwrap();
-    }
-}
-
-struct TestImageImpl {
-    inner: RgbaImage,
-}
-
-impl Image for TestImageImpl {
-    fn new(width: u32, height: u32) -> Self {
-        TestImageImpl {
-            inner: RgbaImage::new(width, height),
-        }
-    }
-
-    fn set_pixel(&mut self, index: (u32, u32), value: Rgba32) {
-        self.inner[(index.0, index.1)] = image::Rgba::from(value.0);
-    }
-}
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Result;
+use sres_emulator::apu::AudioBuffer;
+use sres_emulator::common::logging;
+use sres_emulator::components::cartridge::Cartridge;
+use sres_emulator::components::ppu::Framebuffer;
+use sres_emulator::recording::WavRecorder;
+use sres_emulator::recording::Y4mWavRecorder;
+use sres_emulator::ExecutionResult;
+use sres_emulator::System;
+
+/// Runs a ROM without user interface
+#[derive(argh::FromArgs)]
+struct HeadlessArgs {
+    /// rom file to load
+    #[argh(positional)]
+    rom: PathBuf,
+
+    /// number of frames to run
+    #[argh(option, default = "600")]
+    frames: u64,
+
+    /// record video and audio to <record>.y4m and <record>.wav
+    #[argh(option)]
+    record: Option<PathBuf>,
+
+    /// record only audio to a wav file
+    #[argh(option)]
+    wav: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    logging::init();
+    let args: HeadlessArgs = argh::from_env();
+
+    if args.record.is_some() && args.wav.is_some() {
+        bail!("--record already includes audio, use only one of --record and --wav");
+    }
+
+    let mut emulator = System::with_cartridge(&Cartridge::with_sfc_file(&args.rom)?);
+    if let Some(path) = &args.record {
+        emulator.set_frame_sink(Box::new(Y4mWavRecorder::create(path)?));
+    } else {
+        emulator.force_headless();
+    }
+    if let Some(path) = &args.wav {
+        emulator.set_frame_sink(Box::new(WavRecorder::create(path)?));
+    }
+
+    let mut video_frame = Framebuffer::default();
+    let mut audio_buffer = AudioBuffer::new();
+    for _ in 0..args.frames {
+        let result = emulator.execute_frames(1);
+        emulator.swap_video_frame(&mut video_frame);
+        emulator.swap_audio_buffer(&mut audio_buffer);
+        audio_buffer.clear();
+        match result {
+            ExecutionResult::Normal => (),
+            ExecutionResult::Halt => {
+                println!("CPU halted");
+                break;
+            }
+            ExecutionResult::Break(reason) => {
+                println!("Break: {}", reason.trigger);
+                break;
+            }
+        }
+    }
+    emulator.remove_frame_sink()?;
+    Ok(())
+}